@@ -0,0 +1,189 @@
+use std::{
+    fs,
+    io::{BufReader, Read},
+    path::Path,
+};
+
+use anyhow::Result;
+
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+const WEBP_ANIMATION_FLAG: u8 = 0x02;
+
+const GIF_EXTENSION: u8 = 0x21;
+const GIF_IMAGE_DESCRIPTOR: u8 = 0x2c;
+const GIF_COLOR_TABLE_FLAG: u8 = 0x80;
+
+// Only the headers are read, up to the second frame of a GIF.
+pub(crate) fn is_animated(path: &Path) -> bool {
+    match detect(path) {
+        Ok(animated) => animated,
+        Err(e) => {
+            tracing::trace!("Failed to detect animation of {}: {}", path.display(), e);
+            false
+        }
+    }
+}
+
+fn detect(path: &Path) -> Result<bool> {
+    detect_from(&mut BufReader::new(fs::File::open(path)?))
+}
+
+fn detect_from(reader: &mut impl Read) -> Result<bool> {
+    let mut signature = [0; 8];
+    reader.read_exact(&mut signature)?;
+
+    if signature == PNG_SIGNATURE {
+        is_animated_png(reader)
+    } else if signature.starts_with(b"RIFF") {
+        is_animated_webp(reader)
+    } else if signature.starts_with(b"GIF8") {
+        is_animated_gif(reader)
+    } else {
+        Ok(false)
+    }
+}
+
+// APNG declares its frames in an acTL chunk before the first IDAT.
+fn is_animated_png(reader: &mut impl Read) -> Result<bool> {
+    loop {
+        let mut header = [0; 8];
+        reader.read_exact(&mut header)?;
+        match &header[4..] {
+            b"acTL" => return Ok(true),
+            b"IDAT" | b"IEND" => return Ok(false),
+            _ => {
+                let length = u32::from_be_bytes([header[0], header[1], header[2], header[3]]);
+                skip(reader, u64::from(length) + 4)?;
+            }
+        }
+    }
+}
+
+fn is_animated_webp(reader: &mut impl Read) -> Result<bool> {
+    let mut header = [0; 13];
+    reader.read_exact(&mut header)?;
+    Ok(&header[..8] == b"WEBPVP8X" && header[12] & WEBP_ANIMATION_FLAG != 0)
+}
+
+fn is_animated_gif(reader: &mut impl Read) -> Result<bool> {
+    let mut screen = [0; 5];
+    reader.read_exact(&mut screen)?;
+    skip_color_table(reader, screen[2])?;
+
+    let mut frames = 0;
+    loop {
+        match read_u8(reader)? {
+            GIF_EXTENSION => {
+                read_u8(reader)?;
+                skip_sub_blocks(reader)?;
+            }
+            GIF_IMAGE_DESCRIPTOR => {
+                frames += 1;
+                if frames > 1 {
+                    return Ok(true);
+                }
+                let mut descriptor = [0; 9];
+                reader.read_exact(&mut descriptor)?;
+                skip_color_table(reader, descriptor[8])?;
+                read_u8(reader)?;
+                skip_sub_blocks(reader)?;
+            }
+            _ => return Ok(false),
+        }
+    }
+}
+
+fn skip_color_table(reader: &mut impl Read, packed: u8) -> Result<()> {
+    if packed & GIF_COLOR_TABLE_FLAG != 0 {
+        skip(reader, 3 << ((packed & 0x07) + 1))?;
+    }
+    Ok(())
+}
+
+fn skip_sub_blocks(reader: &mut impl Read) -> Result<()> {
+    loop {
+        match read_u8(reader)? {
+            0 => return Ok(()),
+            length => skip(reader, length.into())?,
+        }
+    }
+}
+
+fn skip(reader: &mut impl Read, length: u64) -> Result<()> {
+    std::io::copy(&mut reader.take(length), &mut std::io::sink())?;
+    Ok(())
+}
+
+fn read_u8(reader: &mut impl Read) -> Result<u8> {
+    let mut byte = [0];
+    reader.read_exact(&mut byte)?;
+    Ok(byte[0])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn png(chunks: &[&[u8; 4]]) -> Vec<u8> {
+        let mut bytes = PNG_SIGNATURE.to_vec();
+        for chunk in chunks {
+            bytes.extend_from_slice(&4u32.to_be_bytes());
+            bytes.extend_from_slice(*chunk);
+            bytes.extend_from_slice(&[0; 8]);
+        }
+        bytes
+    }
+
+    fn gif(frames: usize) -> Vec<u8> {
+        // A 1x1 screen with a two-color global table.
+        let mut bytes = b"GIF89a\x01\x00\x01\x00\x80\x00\x00".to_vec();
+        bytes.extend_from_slice(&[0; 6]);
+        // A graphic control extension before each frame.
+        for _ in 0..frames {
+            bytes.extend_from_slice(&[GIF_EXTENSION, 0xf9, 4, 0, 0, 0, 0, 0]);
+            bytes.extend_from_slice(&[GIF_IMAGE_DESCRIPTOR, 0, 0, 0, 0, 1, 0, 1, 0, 0]);
+            bytes.extend_from_slice(&[2, 2, 0x44, 0x01, 0]);
+        }
+        bytes.push(0x3b);
+        bytes
+    }
+
+    fn webp(chunk: &[u8; 4], flags: u8) -> Vec<u8> {
+        let mut bytes = b"RIFF\x00\x00\x00\x00WEBP".to_vec();
+        bytes.extend_from_slice(chunk);
+        bytes.extend_from_slice(&[10, 0, 0, 0, flags, 0, 0, 0]);
+        bytes
+    }
+
+    fn detect_bytes(bytes: &[u8]) -> Result<bool> {
+        detect_from(&mut &bytes[..])
+    }
+
+    #[test]
+    fn detects_apng() {
+        assert!(detect_bytes(&png(&[b"IHDR", b"acTL", b"IDAT", b"IEND"])).unwrap());
+        assert!(!detect_bytes(&png(&[b"IHDR", b"IDAT", b"acTL", b"IEND"])).unwrap());
+        assert!(!detect_bytes(&png(&[b"IHDR", b"IEND"])).unwrap());
+    }
+
+    #[test]
+    fn detects_animated_gif() {
+        assert!(detect_bytes(&gif(2)).unwrap());
+        assert!(!detect_bytes(&gif(1)).unwrap());
+    }
+
+    #[test]
+    fn detects_animated_webp() {
+        assert!(detect_bytes(&webp(b"VP8X", WEBP_ANIMATION_FLAG)).unwrap());
+        assert!(!detect_bytes(&webp(b"VP8X", 0)).unwrap());
+        assert!(!detect_bytes(&webp(b"VP8 ", WEBP_ANIMATION_FLAG)).unwrap());
+    }
+
+    #[test]
+    fn ignores_other_and_truncated_files() {
+        assert!(!detect_bytes(b"\xff\xd8\xff\xe0\x00\x10JFIF").unwrap());
+        assert!(detect_bytes(&png(&[b"IHDR"])).is_err());
+        assert!(detect_bytes(&gif(2)[..20]).is_err());
+        assert!(detect_bytes(b"GIF8").is_err());
+    }
+}
@@ -0,0 +1,183 @@
+use std::{collections::BTreeMap, time::SystemTime};
+
+use serde::Serialize;
+
+use crate::{
+    model::{File, FilesContainer},
+    template,
+};
+
+#[derive(Serialize)]
+pub(crate) struct Listing<'a> {
+    uri: &'a str,
+    depth: u32,
+    #[serde(flatten)]
+    params: Option<Params<'a>>,
+    containers: Vec<Container<'a>>,
+}
+
+#[derive(Serialize)]
+struct Container<'a> {
+    uri: &'a str,
+    #[serde(flatten)]
+    params: Option<Params<'a>>,
+    page: usize,
+    page_count: usize,
+    files: Vec<Entry<'a>>,
+}
+
+#[derive(Serialize)]
+struct Params<'a> {
+    order: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seed: Option<u64>,
+    reverse: bool,
+    filter_dir: Option<&'a str>,
+    filter_file: Option<&'a str>,
+    per_page: usize,
+}
+
+#[derive(Serialize)]
+struct Entry<'a> {
+    name: &'a str,
+    path: String,
+    #[serde(rename = "type")]
+    media_type: &'static str,
+    mime: Option<&'static str>,
+    size: Option<u64>,
+    modified: Option<u64>,
+    created: Option<u64>,
+    width: Option<u32>,
+    height: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    items: Option<&'a BTreeMap<&'static str, usize>>,
+}
+
+#[derive(Serialize)]
+pub(crate) struct DirTree<'a> {
+    uri: &'a str,
+    dirs: Vec<TreeEntry<'a>>,
+}
+
+#[derive(Serialize)]
+struct TreeEntry<'a> {
+    name: &'a str,
+    path: String,
+    // Percent-encoded, to be used in links as is.
+    href: String,
+}
+
+#[derive(Serialize)]
+pub(crate) struct Playlist<'a> {
+    uri: &'a str,
+    items: Vec<Slide<'a>>,
+}
+
+#[derive(Serialize)]
+struct Slide<'a> {
+    name: &'a str,
+    // Percent-encoded, and converted for formats that browsers cannot show.
+    src: String,
+    #[serde(rename = "type")]
+    media_type: &'static str,
+}
+
+impl<'a> Playlist<'a> {
+    pub fn new(uri: &'a str, files: &[&'a File]) -> Self {
+        Self {
+            uri,
+            items: files
+                .iter()
+                .map(|f| Slide {
+                    name: f.name(),
+                    src: template::display_link(f, &format!("/{}", f.path())),
+                    media_type: if f.is_video() { "video" } else { "image" },
+                })
+                .collect(),
+        }
+    }
+}
+
+impl<'a> DirTree<'a> {
+    pub fn new(uri: &'a str, dirs: &'a [File]) -> Self {
+        Self {
+            uri,
+            dirs: dirs
+                .iter()
+                .map(|d| TreeEntry {
+                    name: d.name(),
+                    path: d.to_uri(),
+                    href: format!("/{}", d.path()),
+                })
+                .collect(),
+        }
+    }
+}
+
+impl<'a> Listing<'a> {
+    // Reports the parameters that `walk_dir` has applied, which subdirectories with their own
+    // config may override.
+    pub fn new(uri: &'a str, depth: u32, containers: &'a [FilesContainer]) -> Self {
+        Self {
+            uri,
+            depth,
+            params: containers.first().and_then(Params::new),
+            containers: containers.iter().map(Container::new).collect(),
+        }
+    }
+}
+
+impl<'a> Container<'a> {
+    fn new(container: &'a FilesContainer) -> Self {
+        Self {
+            uri: container.uri(),
+            params: Params::new(container),
+            page: container.page(),
+            page_count: container.page_count(),
+            files: container
+                .files()
+                .iter()
+                .filter(|f| f.name() != "..")
+                .map(Entry::new)
+                .collect(),
+        }
+    }
+}
+
+impl<'a> Params<'a> {
+    fn new(container: &'a FilesContainer) -> Option<Self> {
+        let params = container.params()?;
+        Some(Self {
+            order: params.order.name(),
+            seed: params.order.seed(),
+            reverse: params.reverse,
+            filter_dir: params.filter_dir.as_deref(),
+            filter_file: params.filter_file.as_deref(),
+            per_page: params.per_page,
+        })
+    }
+}
+
+impl<'a> Entry<'a> {
+    fn new(file: &'a File) -> Self {
+        let dimensions = file.dimensions();
+        Self {
+            name: file.name(),
+            path: file.to_uri(),
+            media_type: file.type_name(),
+            mime: file.mime(),
+            size: file.size(),
+            modified: file.modified_at().and_then(unix_time),
+            created: file.created_at().and_then(unix_time),
+            width: dimensions.map(|(w, _)| w),
+            height: dimensions.map(|(_, h)| h),
+            items: file.stats().map(|s| s.counts()),
+        }
+    }
+}
+
+fn unix_time(time: SystemTime) -> Option<u64> {
+    time.duration_since(SystemTime::UNIX_EPOCH)
+        .ok()
+        .map(|d| d.as_secs())
+}
@@ -0,0 +1,304 @@
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap},
+    fs::{File, FileTimes, Metadata},
+    hash::{Hash, Hasher},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        OnceLock, RwLock,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{anyhow, bail, Result};
+use rand::Rng;
+
+// Extensions of the files that the generators write. Anything else in the cache directory
+// is never served, since its content type would be derived from the extension.
+const CACHED_EXTENSIONS: &[&str] = &[
+    "avif", "bmp", "gif", "ico", "jpg", "png", "svg", "tiff", "webp",
+];
+
+// How stale the access time of a cached file may get before a hit refreshes it.
+const ACCESS_TIME_RESOLUTION: Duration = Duration::from_secs(60 * 60);
+
+// The extension of each cached file by key, for each cache directory. Each directory is read
+// once, so that a lookup does not have to list it.
+type Index = RwLock<HashMap<PathBuf, HashMap<String, &'static str>>>;
+
+static INDEX: OnceLock<Index> = OnceLock::new();
+
+// Bytes written since the cache was pruned last. It starts high to prune on the first write.
+static WRITTEN: AtomicU64 = AtomicU64::new(u64::MAX / 2);
+
+pub(crate) fn get_or_create<F>(
+    cache_dir: &Path,
+    max_size: u64,
+    source: &Path,
+    variant: &str,
+    create: F,
+) -> Result<PathBuf>
+where
+    F: FnOnce() -> Result<(Vec<u8>, &'static str)>,
+{
+    let key = cache_key(source, variant)?;
+    if let Some((path, metadata)) = find_cached(cache_dir, &key) {
+        tracing::trace!("cache hit {} for {}", key, source.display());
+        touch(&path, &metadata);
+        return Ok(path);
+    }
+
+    let (bytes, extension) = create()?;
+    let Some(&extension) = CACHED_EXTENSIONS.iter().find(|&&e| e == extension) else {
+        bail!("Refused to cache a .{} file", extension);
+    };
+    let size = bytes.len() as u64;
+    let path = cache_dir.join(format!("{}.{}", key, extension));
+    let tmp_path = cache_dir.join(format!(
+        "{}.{:016x}.tmp",
+        key,
+        rand::thread_rng().gen::<u64>()
+    ));
+    std::fs::write(&tmp_path, bytes)?;
+    if let Err(e) = std::fs::rename(&tmp_path, &path) {
+        let _ = std::fs::remove_file(&tmp_path);
+        return Err(e.into());
+    }
+    with_index(cache_dir, |index| index.insert(key, extension));
+
+    tracing::debug!("cached {} for {}", path.display(), source.display());
+    if max_size > 0 && WRITTEN.fetch_add(size, Ordering::Relaxed) + size > max_size / 16 {
+        WRITTEN.store(0, Ordering::Relaxed);
+        if let Err(e) = prune(cache_dir, max_size) {
+            tracing::warn!("Failed to prune {}: {:?}", cache_dir.display(), e);
+        }
+    }
+    Ok(path)
+}
+
+fn cache_key(source: &Path, variant: &str) -> Result<String> {
+    let metadata = std::fs::metadata(source)?;
+    let modified = metadata
+        .modified()?
+        .duration_since(UNIX_EPOCH)
+        .map_err(|e| anyhow!(e))?;
+
+    let mut hasher = DefaultHasher::new();
+    source.canonicalize()?.hash(&mut hasher);
+    modified.hash(&mut hasher);
+    metadata.len().hash(&mut hasher);
+    variant.hash(&mut hasher);
+    Ok(format!("{:016x}", hasher.finish()))
+}
+
+fn find_cached(cache_dir: &Path, key: &str) -> Option<(PathBuf, Metadata)> {
+    let extension = with_index(cache_dir, |index| index.get(key).copied())?;
+    let path = cache_dir.join(format!("{}.{}", key, extension));
+    match std::fs::metadata(&path) {
+        Ok(metadata) if metadata.is_file() => Some((path, metadata)),
+        _ => {
+            with_index(cache_dir, |index| index.remove(key));
+            None
+        }
+    }
+}
+
+fn with_index<T>(cache_dir: &Path, f: impl FnOnce(&mut HashMap<String, &'static str>) -> T) -> T {
+    let mut index = INDEX.get_or_init(Default::default).write().unwrap();
+    let files = index
+        .entry(cache_dir.to_owned())
+        .or_insert_with(|| read_index(cache_dir));
+    f(files)
+}
+
+fn read_index(cache_dir: &Path) -> HashMap<String, &'static str> {
+    let Ok(entries) = std::fs::read_dir(cache_dir) else {
+        return HashMap::new();
+    };
+    entries
+        .flatten()
+        .filter_map(|entry| {
+            let path = entry.path();
+            let extension = cached_extension(&path)?;
+            let key = path.file_stem()?.to_str()?.to_owned();
+            Some((key, extension))
+        })
+        .collect()
+}
+
+fn cached_extension(path: &Path) -> Option<&'static str> {
+    let extension = path.extension()?.to_str()?;
+    CACHED_EXTENSIONS.iter().copied().find(|&e| e == extension)
+}
+
+// Pruning goes by the access time, which unlike the modification time is not sent as
+// `Last-Modified`, so conditional requests keep working.
+fn touch(path: &Path, metadata: &Metadata) {
+    let now = SystemTime::now();
+    let accessed = metadata.accessed().unwrap_or(UNIX_EPOCH);
+    if now.duration_since(accessed).unwrap_or_default() < ACCESS_TIME_RESOLUTION {
+        return;
+    }
+    if let Err(e) = File::open(path).and_then(|f| f.set_times(FileTimes::new().set_accessed(now))) {
+        tracing::debug!("Failed to touch {}: {:?}", path.display(), e);
+    }
+}
+
+// Removes the least recently used files until the cache fits in `max_size` bytes.
+fn prune(cache_dir: &Path, max_size: u64) -> Result<()> {
+    let mut files = Vec::new();
+    let mut total = 0;
+    for entry in std::fs::read_dir(cache_dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        let Ok(metadata) = entry.metadata() else {
+            continue;
+        };
+        if !metadata.is_file() || cached_extension(&path).is_none() {
+            continue;
+        }
+        total += metadata.len();
+        files.push((
+            metadata.accessed().unwrap_or(UNIX_EPOCH),
+            metadata.len(),
+            path,
+        ));
+    }
+    if total <= max_size {
+        return Ok(());
+    }
+
+    files.sort_unstable();
+    let mut removed = 0;
+    for (_, size, path) in files {
+        if total <= max_size {
+            break;
+        }
+        match std::fs::remove_file(&path) {
+            Ok(()) => {
+                total -= size;
+                removed += 1;
+                if let Some(key) = path.file_stem().and_then(|k| k.to_str()) {
+                    with_index(cache_dir, |index| index.remove(key));
+                }
+            }
+            Err(e) => tracing::debug!("Failed to remove {}: {:?}", path.display(), e),
+        }
+    }
+    tracing::debug!("pruned {} files from {}", removed, cache_dir.display());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use super::*;
+
+    // A fresh directory under the temp directory, removed when dropped.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new() -> Self {
+            let dir = std::env::temp_dir().join(format!(
+                "rattice-test-{:016x}",
+                rand::thread_rng().gen::<u64>()
+            ));
+            std::fs::create_dir_all(&dir).unwrap();
+            Self(dir)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn set_accessed(path: &Path, accessed: SystemTime) {
+        File::open(path)
+            .unwrap()
+            .set_times(FileTimes::new().set_accessed(accessed))
+            .unwrap();
+    }
+
+    #[test]
+    fn keys_depend_on_variant_and_content() {
+        let dir = TempDir::new();
+        let source = dir.0.join("a.png");
+        std::fs::write(&source, b"a").unwrap();
+
+        let key = cache_key(&source, "thumbnail-128").unwrap();
+        assert_eq!(key, cache_key(&source, "thumbnail-128").unwrap());
+        assert_ne!(key, cache_key(&source, "thumbnail-256").unwrap());
+
+        std::fs::write(&source, b"ab").unwrap();
+        assert_ne!(key, cache_key(&source, "thumbnail-128").unwrap());
+    }
+
+    #[test]
+    fn creates_once_and_keeps_modification_time() {
+        let dir = TempDir::new();
+        let source = dir.0.join("a.png");
+        std::fs::write(&source, b"a").unwrap();
+        let created = Cell::new(0);
+        let create = || {
+            created.set(created.get() + 1);
+            Ok((b"thumbnail".to_vec(), "png"))
+        };
+
+        let path = get_or_create(&dir.0, 0, &source, "thumbnail-128", create).unwrap();
+        assert_eq!(path.extension().unwrap(), "png");
+        let modified = std::fs::metadata(&path).unwrap().modified().unwrap();
+        set_accessed(&path, UNIX_EPOCH);
+
+        let hit = get_or_create(&dir.0, 0, &source, "thumbnail-128", create).unwrap();
+        assert_eq!(hit, path);
+        assert_eq!(created.get(), 1);
+        let metadata = std::fs::metadata(&path).unwrap();
+        assert_eq!(metadata.modified().unwrap(), modified);
+        assert!(metadata.accessed().unwrap() > UNIX_EPOCH);
+    }
+
+    #[test]
+    fn ignores_other_files() {
+        let dir = TempDir::new();
+        let source = dir.0.join("a.png");
+        std::fs::write(&source, b"a").unwrap();
+        let key = cache_key(&source, "format-png").unwrap();
+        std::fs::write(dir.0.join(format!("{}.html", key)), b"<script>").unwrap();
+
+        let path = get_or_create(&dir.0, 0, &source, "format-png", || {
+            Ok((b"png".to_vec(), "png"))
+        })
+        .unwrap();
+        assert_eq!(path, dir.0.join(format!("{}.png", key)));
+        assert!(get_or_create(&dir.0, 0, &source, "render", || {
+            Ok((b"<html>".to_vec(), "html"))
+        })
+        .is_err());
+    }
+
+    #[test]
+    fn prunes_least_recently_used_files() {
+        let dir = TempDir::new();
+        let files = ["old.png", "recent.jpg", "newest.webp"];
+        for (i, name) in files.iter().enumerate() {
+            let path = dir.0.join(name);
+            std::fs::write(&path, [0; 100]).unwrap();
+            set_accessed(&path, UNIX_EPOCH + Duration::from_secs(1000 * i as u64));
+        }
+        std::fs::write(dir.0.join("other.txt"), [0; 1000]).unwrap();
+
+        prune(&dir.0, 250).unwrap();
+        let exists = |name: &str| dir.0.join(name).exists();
+        assert!(!exists("old.png"));
+        assert!(exists("recent.jpg"));
+        assert!(exists("newest.webp"));
+        assert!(exists("other.txt"));
+
+        prune(&dir.0, 0).unwrap();
+        assert!(!exists("recent.jpg"));
+        assert!(!exists("newest.webp"));
+    }
+}
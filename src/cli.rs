@@ -1,4 +1,4 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use anyhow::{anyhow, bail, Result};
 use clap::{AppSettings::DeriveDisplayOrder, ArgEnum, Parser};
@@ -20,9 +20,18 @@ const DEFAULT_BIND_ADDRESS: &str = "0.0.0.0";
 #[derive(ArgEnum, Clone, Debug)]
 enum SortBy {
     Name,
+    Natural,
+    IgnoreCase,
+    Collation,
     #[cfg(not(all(target_os = "linux", target_env = "musl")))]
     Created,
     Modified,
+    Taken,
+    Pixels,
+    Aspect,
+    Size,
+    Extension,
+    Random,
 }
 
 #[derive(Parser, Debug)]
@@ -54,6 +63,19 @@ pub struct Opt {
     #[clap(arg_enum, short, long, default_value = "name", env = "RATTICE_SORT_BY")]
     sort_by: SortBy,
 
+    /// Seed for the random sort order
+    #[clap(long, default_value = "0", env = "RATTICE_SEED")]
+    pub seed: u64,
+
+    /// Max Hamming distance between perceptual hashes of duplicate images
+    #[clap(long, default_value = "10", env = "RATTICE_DUPLICATE_THRESHOLD")]
+    pub duplicate_threshold: u32,
+
+    /// Max number of files per page (0 for unlimited). Subdirectories listed along with a
+    /// directory only show their first page
+    #[clap(long, default_value = "0", env = "RATTICE_PER_PAGE")]
+    pub per_page: usize,
+
     /// Max display depth of the directory tree
     #[clap(short = 'D', long, default_value = "1", env = "RATTICE_DEPTH")]
     pub depth: u32,
@@ -106,6 +128,14 @@ pub struct Opt {
     #[clap(short = 'x', long, env = "RATTICE_REAL_IP_HEADER")]
     pub real_ip_header: Option<String>,
 
+    /// Directory to cache generated images (default: rattice-<uid> in temp directory)
+    #[clap(short = 'C', long, parse(from_os_str), env = "RATTICE_CACHE_DIR")]
+    pub cache_dir: Option<PathBuf>,
+
+    /// Max total size of generated images in MiB (0 for unlimited)
+    #[clap(long, default_value = "1024", env = "RATTICE_CACHE_SIZE")]
+    pub cache_size: u64,
+
     /// Reverse sort order
     #[clap(help_heading = "FLAGS")]
     #[clap(short, long, env = "RATTICE_REVERSE")]
@@ -116,6 +146,46 @@ pub struct Opt {
     #[clap(short, long, env = "RATTICE_EAGER")]
     pub eager: bool,
 
+    /// Play animated images in the grid without hovering
+    #[clap(help_heading = "FLAGS")]
+    #[clap(long, env = "RATTICE_ANIMATE")]
+    pub animate: bool,
+
+    /// Read EXIF metadata of images
+    #[clap(help_heading = "FLAGS")]
+    #[clap(long, env = "RATTICE_EXIF")]
+    pub exif: bool,
+
+    /// Detect media types from file contents instead of extensions
+    #[clap(help_heading = "FLAGS")]
+    #[clap(long, env = "RATTICE_SNIFF_CONTENT")]
+    pub sniff_content: bool,
+
+    /// Read image dimensions from file headers to reserve layout space
+    #[clap(help_heading = "FLAGS")]
+    #[clap(long, env = "RATTICE_PROBE_DIMENSIONS")]
+    pub probe_dimensions: bool,
+
+    /// Strip scripts and external references from SVG images
+    #[clap(help_heading = "FLAGS")]
+    #[clap(long, env = "RATTICE_SANITIZE_SVG")]
+    pub sanitize_svg: bool,
+
+    /// Rasterize SVG images for thumbnails
+    #[clap(help_heading = "FLAGS")]
+    #[clap(long, env = "RATTICE_RASTERIZE_SVG")]
+    pub rasterize_svg: bool,
+
+    /// Show the total size of directories including their subdirectories
+    #[clap(help_heading = "FLAGS")]
+    #[clap(long, env = "RATTICE_DIRECTORY_SIZES")]
+    pub directory_sizes: bool,
+
+    /// Disable server-side thumbnail generation
+    #[clap(help_heading = "FLAGS")]
+    #[clap(long, env = "RATTICE_NO_THUMBNAIL")]
+    pub no_thumbnail: bool,
+
     /// Ignore query parameters
     #[clap(help_heading = "FLAGS")]
     #[clap(short, long, env = "RATTICE_IGNORE_QUERY_PARAMS")]
@@ -204,6 +274,9 @@ impl Opt {
         if let Some(key) = opt.server_key {
             opt.server_key = Some(key.canonicalize()?);
         }
+        let cache_dir = opt.cache_dir.take().unwrap_or_else(default_cache_dir);
+        create_cache_dir(&cache_dir)?;
+        opt.cache_dir = Some(cache_dir.canonicalize()?);
 
         if !opt.title_prefix.is_empty() && !opt.title_prefix.ends_with(' ') {
             opt.title_prefix.push(' ');
@@ -223,6 +296,7 @@ impl Opt {
             .ok_or(anyhow!("Invalid sort order"))?
             .get_name()
             .parse()
+            .map(|o: SortOrder| o.with_seed(self.seed))
             .map_err(|e: String| anyhow!(e))
     }
 }
@@ -246,3 +320,41 @@ fn get_random_string(length: u8) -> String {
         })
         .collect()
 }
+
+#[cfg(unix)]
+fn default_cache_dir() -> PathBuf {
+    let uid = unsafe { libc::geteuid() };
+    std::env::temp_dir().join(format!("rattice-{}", uid))
+}
+
+#[cfg(windows)]
+fn default_cache_dir() -> PathBuf {
+    std::env::temp_dir().join("rattice")
+}
+
+// Cached files are served as they are, so nobody else may be able to put files there.
+#[cfg(unix)]
+fn create_cache_dir(dir: &Path) -> Result<()> {
+    use std::os::unix::fs::{DirBuilderExt, MetadataExt};
+
+    std::fs::DirBuilder::new()
+        .recursive(true)
+        .mode(0o700)
+        .create(dir)?;
+    let metadata = std::fs::metadata(dir)?;
+    if metadata.uid() != unsafe { libc::geteuid() } {
+        bail!("Cache directory {} is owned by another user", dir.display());
+    }
+    if metadata.mode() & 0o022 != 0 {
+        bail!(
+            "Cache directory {} is writable by other users",
+            dir.display()
+        );
+    }
+    Ok(())
+}
+
+#[cfg(windows)]
+fn create_cache_dir(dir: &Path) -> Result<()> {
+    Ok(std::fs::create_dir_all(dir)?)
+}
@@ -0,0 +1,104 @@
+use std::{cell::RefCell, cmp::Ordering, iter::Peekable, str::Chars};
+
+use feruca::Collator;
+
+thread_local! {
+    // A collator keeps buffers between comparisons, so each sorting thread gets its own.
+    static COLLATOR: RefCell<Collator> = RefCell::new(Collator::default());
+}
+
+// Compares runs of digits by their value, so that `frame2` comes before `frame10`.
+// Ties such as `a01` and `a1` fall back to `case_insensitive_cmp`, which keeps the order total.
+pub(crate) fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let (mut a_chars, mut b_chars) = (a.chars().peekable(), b.chars().peekable());
+    loop {
+        let ordering = match (a_chars.peek(), b_chars.peek()) {
+            (None, None) => return case_insensitive_cmp(a, b),
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let (x, y) = (take_number(&mut a_chars), take_number(&mut b_chars));
+                let (x, y) = (x.trim_start_matches('0'), y.trim_start_matches('0'));
+                x.len().cmp(&y.len()).then_with(|| x.cmp(y))
+            }
+            (Some(&x), Some(&y)) => {
+                a_chars.next();
+                b_chars.next();
+                x.to_lowercase().cmp(y.to_lowercase())
+            }
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+}
+
+pub(crate) fn case_insensitive_cmp(a: &str, b: &str) -> Ordering {
+    a.chars()
+        .flat_map(char::to_lowercase)
+        .cmp(b.chars().flat_map(char::to_lowercase))
+        .then_with(|| a.cmp(b))
+}
+
+// Uses the root collation of the CLDR, which orders accented and non-Latin letters
+// the way most languages expect rather than by code point.
+pub(crate) fn collation_cmp(a: &str, b: &str) -> Ordering {
+    COLLATOR.with(|c| c.borrow_mut().collate(a, b))
+}
+
+fn take_number(chars: &mut Peekable<Chars>) -> String {
+    let mut number = String::new();
+    while let Some(c) = chars.next_if(char::is_ascii_digit) {
+        number.push(c);
+    }
+    number
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sorted(mut names: Vec<&str>, cmp: fn(&str, &str) -> Ordering) -> Vec<&str> {
+        names.sort_by(|a, b| cmp(a, b));
+        names
+    }
+
+    #[test]
+    fn orders_numbers_by_value() {
+        assert_eq!(
+            sorted(
+                vec!["frame10", "frame2", "Frame1", "frame1.5", "frame", "frame02"],
+                natural_cmp
+            ),
+            vec!["frame", "Frame1", "frame1.5", "frame02", "frame2", "frame10"]
+        );
+    }
+
+    #[test]
+    fn keeps_natural_order_total() {
+        assert_eq!(natural_cmp("a01", "a1"), Ordering::Less);
+        assert_eq!(natural_cmp("a1", "a01"), Ordering::Greater);
+        assert_eq!(natural_cmp("A1", "a1"), Ordering::Less);
+        assert_eq!(natural_cmp("a1", "a1"), Ordering::Equal);
+        assert_eq!(
+            natural_cmp("x99999999999999999999999", "x100000000000000000000000"),
+            Ordering::Less
+        );
+    }
+
+    #[test]
+    fn ignores_case() {
+        assert_eq!(
+            sorted(vec!["b", "B", "a", "Ä", "A"], case_insensitive_cmp),
+            vec!["A", "a", "B", "b", "Ä"]
+        );
+    }
+
+    #[test]
+    fn collates_accents_with_base_letters() {
+        assert_eq!(
+            sorted(vec!["zebra", "Émile", "eagle", "apple"], collation_cmp),
+            vec!["apple", "eagle", "Émile", "zebra"]
+        );
+    }
+}
@@ -1,5 +1,30 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use serde::Deserialize;
+
 use crate::model::SortOrder;
 
+pub(crate) const DIR_CONFIG_FILE_NAME: &str = ".rattice.toml";
+
+// The subset of `Config` that a `.rattice.toml` in a directory can override.
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct DirConfig {
+    // Applies the file to the subdirectories too, unless they have their own.
+    inherit: bool,
+    sort_by: Option<String>,
+    seed: Option<u64>,
+    reverse: Option<bool>,
+    depth: Option<u32>,
+    filter_dir: Option<String>,
+    filter_file: Option<String>,
+    lazy: Option<bool>,
+    title_prefix: Option<String>,
+}
+
 #[derive(Clone)]
 pub struct Config {
     lazy: bool,
@@ -10,6 +35,20 @@ pub struct Config {
     ignore_query_params: bool,
     filter_dir_pattern: Option<String>,
     filter_file_pattern: Option<String>,
+    thumbnail: bool,
+    cache_dir: PathBuf,
+    cache_size: u64,
+    exif: bool,
+    sniff_content: bool,
+    probe_dimensions: bool,
+    duplicate_threshold: u32,
+    animate: bool,
+    sanitize_svg: bool,
+    rasterize_svg: bool,
+    per_page: usize,
+    directory_sizes: bool,
+    // The configuration before any `.rattice.toml` was applied.
+    defaults: Option<Arc<Config>>,
 }
 
 impl Config {
@@ -23,6 +62,18 @@ impl Config {
         ignore_query_params: bool,
         filter_dir_pattern: Option<String>,
         filter_file_pattern: Option<String>,
+        thumbnail: bool,
+        cache_dir: PathBuf,
+        cache_size: u64,
+        exif: bool,
+        sniff_content: bool,
+        probe_dimensions: bool,
+        duplicate_threshold: u32,
+        animate: bool,
+        sanitize_svg: bool,
+        rasterize_svg: bool,
+        per_page: usize,
+        directory_sizes: bool,
     ) -> Self {
         Self {
             lazy,
@@ -33,6 +84,19 @@ impl Config {
             ignore_query_params,
             filter_dir_pattern,
             filter_file_pattern,
+            thumbnail,
+            cache_dir,
+            cache_size,
+            exif,
+            sniff_content,
+            probe_dimensions,
+            duplicate_threshold,
+            animate,
+            sanitize_svg,
+            rasterize_svg,
+            per_page,
+            directory_sizes,
+            defaults: None,
         }
     }
 
@@ -67,4 +131,209 @@ impl Config {
     pub fn filter_file_pattern(&self) -> Option<&str> {
         self.filter_file_pattern.as_deref()
     }
+
+    pub fn thumbnail(&self) -> bool {
+        self.thumbnail
+    }
+
+    pub fn cache_dir(&self) -> &Path {
+        &self.cache_dir
+    }
+
+    pub fn cache_size(&self) -> u64 {
+        self.cache_size
+    }
+
+    pub fn exif(&self) -> bool {
+        self.exif
+    }
+
+    pub fn sniff_content(&self) -> bool {
+        self.sniff_content
+    }
+
+    pub fn probe_dimensions(&self) -> bool {
+        self.probe_dimensions
+    }
+
+    pub fn duplicate_threshold(&self) -> u32 {
+        self.duplicate_threshold
+    }
+
+    pub fn animate(&self) -> bool {
+        self.animate
+    }
+
+    pub fn sanitize_svg(&self) -> bool {
+        self.sanitize_svg
+    }
+
+    pub fn rasterize_svg(&self) -> bool {
+        self.rasterize_svg
+    }
+
+    pub fn per_page(&self) -> usize {
+        self.per_page
+    }
+
+    pub fn directory_sizes(&self) -> bool {
+        self.directory_sizes
+    }
+
+    // Applies the `.rattice.toml` of each directory from the root down to `uri`, so that
+    // nearer files win. Files above `uri` only count when they set `inherit`. It starts over
+    // from the defaults when called on the configuration of another directory.
+    pub(crate) fn for_dir(self: &Arc<Self>, uri: &str) -> Arc<Self> {
+        self.for_dir_with(uri, DirConfig::read)
+    }
+
+    fn for_dir_with(
+        self: &Arc<Self>,
+        uri: &str,
+        read: impl Fn(&str) -> Option<DirConfig>,
+    ) -> Arc<Self> {
+        let defaults = self.defaults.clone().unwrap_or_else(|| self.clone());
+        let mut dirs = vec![String::new()];
+        for component in uri.split('/').filter(|c| !c.is_empty()) {
+            dirs.push(format!("{}/{}", dirs.last().unwrap(), component));
+        }
+
+        let mut config = None;
+        for (i, dir) in dirs.iter().enumerate() {
+            let Some(dir_config) = read(dir) else {
+                continue;
+            };
+            if dir_config.inherit || i == dirs.len() - 1 {
+                dir_config.apply(config.get_or_insert_with(|| Config {
+                    defaults: Some(defaults.clone()),
+                    ..defaults.as_ref().clone()
+                }));
+            }
+        }
+        config.map_or(defaults, Arc::new)
+    }
+}
+
+impl DirConfig {
+    fn read(dir: &str) -> Option<Self> {
+        let path = PathBuf::from(format!(".{}/{}", dir, DIR_CONFIG_FILE_NAME));
+        let text = std::fs::read_to_string(&path).ok()?;
+        match toml::from_str(&text) {
+            Ok(dir_config) => Some(dir_config),
+            Err(e) => {
+                tracing::warn!("Ignored {}: {}", path.display(), e);
+                None
+            }
+        }
+    }
+
+    fn apply(&self, config: &mut Config) {
+        if let Some(sort_by) = &self.sort_by {
+            match sort_by.parse::<SortOrder>() {
+                Ok(order) => config.sort_order = order,
+                Err(e) => tracing::warn!("Ignored sort_by in {}: {}", DIR_CONFIG_FILE_NAME, e),
+            }
+        }
+        if let Some(seed) = self.seed {
+            config.sort_order = config.sort_order.clone().with_seed(seed);
+        }
+        if let Some(reverse) = self.reverse {
+            config.reverse = reverse;
+        }
+        if let Some(depth) = self.depth {
+            config.depth = depth;
+        }
+        if let Some(pattern) = &self.filter_dir {
+            config.filter_dir_pattern = Some(pattern.clone());
+        }
+        if let Some(pattern) = &self.filter_file {
+            config.filter_file_pattern = Some(pattern.clone());
+        }
+        if let Some(lazy) = self.lazy {
+            config.lazy = lazy;
+        }
+        if let Some(title_prefix) = &self.title_prefix {
+            config.title_prefix = title_prefix.clone();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    fn defaults() -> Arc<Config> {
+        Arc::new(Config::new(
+            false,
+            String::new(),
+            SortOrder::Name,
+            false,
+            1,
+            false,
+            None,
+            None,
+            false,
+            PathBuf::new(),
+            0,
+            false,
+            false,
+            false,
+            10,
+            false,
+            false,
+            false,
+            0,
+            false,
+        ))
+    }
+
+    // Reads the `.rattice.toml` of each directory from `files` rather than from the disk.
+    fn for_dir(config: &Arc<Config>, uri: &str, files: &[(&str, &str)]) -> Arc<Config> {
+        let files = files.iter().copied().collect::<HashMap<_, _>>();
+        config.for_dir_with(uri, |dir| {
+            files.get(dir).map(|text| toml::from_str(text).unwrap())
+        })
+    }
+
+    #[test]
+    fn applies_the_file_of_the_directory() {
+        let files = [("/a", "sort_by = \"modified\"\nreverse = true\ndepth = 2")];
+        let config = for_dir(&defaults(), "/a/", &files);
+        assert_eq!(config.sort_order().name(), "modified");
+        assert!(config.reverse());
+        assert_eq!(config.depth(), 2);
+        assert!(!config.lazy());
+
+        let defaults = defaults();
+        assert!(Arc::ptr_eq(&for_dir(&defaults, "/b/", &files), &defaults));
+    }
+
+    #[test]
+    fn applies_files_above_only_with_inherit() {
+        let files = [("/a", "reverse = true"), ("/a/b", "lazy = true")];
+        let config = for_dir(&defaults(), "/a/b/", &files);
+        assert!(!config.reverse());
+        assert!(config.lazy());
+
+        let files = [
+            ("", "inherit = true\nfilter_file = \"x\""),
+            ("/a", "inherit = true\nreverse = true\nfilter_file = \"y\""),
+            ("/a/b", "lazy = true"),
+        ];
+        let config = for_dir(&defaults(), "/a/b/", &files);
+        assert!(config.reverse());
+        assert!(config.lazy());
+        assert_eq!(config.filter_file_pattern(), Some("y"));
+    }
+
+    #[test]
+    fn starts_over_from_the_defaults() {
+        let files = [("/a", "reverse = true"), ("/b", "lazy = true")];
+        let config = for_dir(&defaults(), "/a/", &files);
+        let config = for_dir(&config, "/b/", &files);
+        assert!(!config.reverse());
+        assert!(config.lazy());
+    }
 }
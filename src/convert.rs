@@ -0,0 +1,112 @@
+use std::{io::Cursor, path::Path, str::FromStr};
+
+use anyhow::Result;
+use image::{
+    codecs::jpeg::JpegEncoder, metadata::Orientation, DynamicImage, ImageDecoder, ImageFormat,
+    ImageReader,
+};
+
+use crate::{model::MediaType, raw, svg};
+
+pub(crate) const DEFAULT_CONVERSION_FORMAT: OutputFormat = OutputFormat::Png;
+
+const JPEG_QUALITY: u8 = 85;
+
+#[derive(Clone, Copy)]
+pub(crate) enum OutputFormat {
+    Png,
+    Webp,
+    Jpeg,
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "png" => Ok(Self::Png),
+            "webp" => Ok(Self::Webp),
+            "jpeg" | "jpg" => Ok(Self::Jpeg),
+            _ => Err(format!("Invalid format: {}", s)),
+        }
+    }
+}
+
+impl OutputFormat {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Png => "png",
+            Self::Webp => "webp",
+            Self::Jpeg => "jpeg",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Png => "png",
+            Self::Webp => "webp",
+            Self::Jpeg => "jpg",
+        }
+    }
+}
+
+pub(crate) fn convert(path: &Path, format: OutputFormat) -> Result<(Vec<u8>, &'static str)> {
+    let image = decode(path)?;
+    Ok((encode(&image, format)?, format.extension()))
+}
+
+// Derived images are encoded without EXIF, so the orientation is baked into the pixels.
+pub(crate) fn decode(path: &Path) -> Result<DynamicImage> {
+    if matches!(MediaType::new(path), MediaType::Raw) {
+        let (bytes, orientation) = raw::extract_preview(path)?;
+        return decode_bytes(bytes, Some(orientation));
+    }
+    if svg::is_svg(path) {
+        return svg::rasterize(path);
+    }
+
+    let mut decoder = ImageReader::open(path)?
+        .with_guessed_format()?
+        .into_decoder()?;
+    let orientation = decoder.orientation()?;
+    let mut image = DynamicImage::from_decoder(decoder)?;
+    image.apply_orientation(orientation);
+    Ok(image)
+}
+
+// The orientation overrides the one embedded in the bytes, if any.
+pub(crate) fn decode_bytes(
+    bytes: Vec<u8>,
+    orientation: Option<Orientation>,
+) -> Result<DynamicImage> {
+    let mut decoder = ImageReader::new(Cursor::new(bytes))
+        .with_guessed_format()?
+        .into_decoder()?;
+    let orientation = match orientation {
+        Some(orientation) => orientation,
+        None => decoder.orientation()?,
+    };
+    let mut image = DynamicImage::from_decoder(decoder)?;
+    image.apply_orientation(orientation);
+    Ok(image)
+}
+
+pub(crate) fn encode(image: &DynamicImage, format: OutputFormat) -> Result<Vec<u8>> {
+    let mut bytes = vec![];
+    match format {
+        OutputFormat::Png => image.write_to(&mut Cursor::new(&mut bytes), ImageFormat::Png)?,
+        OutputFormat::Webp => {
+            let image = if image.color().has_alpha() {
+                DynamicImage::ImageRgba8(image.to_rgba8())
+            } else {
+                DynamicImage::ImageRgb8(image.to_rgb8())
+            };
+            image.write_to(&mut Cursor::new(&mut bytes), ImageFormat::WebP)?
+        }
+        OutputFormat::Jpeg => {
+            let encoder = JpegEncoder::new_with_quality(&mut bytes, JPEG_QUALITY);
+            image.to_rgb8().write_with_encoder(encoder)?
+        }
+    }
+    Ok(bytes)
+}
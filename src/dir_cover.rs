@@ -0,0 +1,95 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::{Mutex, OnceLock},
+};
+
+use crate::{
+    dir_mtimes::DirMtimes,
+    model::{File, SortOrder},
+};
+
+const COVER_STEMS: &[&str] = &["cover", "folder"];
+const COVER_EXTENSIONS: &[&str] = &["jpg", "jpeg", "png", "webp"];
+
+// Bounds the descent into directories without images of their own.
+const MAX_DEPTH: u32 = 3;
+const MAX_DIRS: usize = 64;
+
+struct Lookup {
+    dirs: DirMtimes,
+    cover: Option<PathBuf>,
+}
+
+type LookupKey = (PathBuf, &'static str, Option<u64>, bool);
+
+static LOOKUPS: OnceLock<Mutex<HashMap<LookupKey, Lookup>>> = OnceLock::new();
+
+// Prefers an explicit `cover.jpg` or `folder.jpg`, then the first image by `order`,
+// then the cover of the first subdirectory that has one.
+pub(crate) fn find(dir: &Path, order: &SortOrder, reverse: bool) -> Option<PathBuf> {
+    let key = (dir.to_owned(), order.name(), order.seed(), reverse);
+    let lookups = LOOKUPS.get_or_init(Default::default);
+    if let Some(lookup) = lookups.lock().unwrap().get(&key) {
+        if lookup.dirs.is_fresh(None) == Some(true) {
+            return lookup.cover.clone();
+        }
+    }
+
+    let mut dirs = DirMtimes::default();
+    let cover = search(dir, order, reverse, 0, &mut dirs);
+    lookups.lock().unwrap().insert(
+        key,
+        Lookup {
+            dirs,
+            cover: cover.clone(),
+        },
+    );
+    cover
+}
+
+fn search(
+    dir: &Path,
+    order: &SortOrder,
+    reverse: bool,
+    depth: u32,
+    dirs: &mut DirMtimes,
+) -> Option<PathBuf> {
+    if depth > MAX_DEPTH || dirs.len() >= MAX_DIRS {
+        return None;
+    }
+    dirs.record(dir)?;
+
+    let mut files = fs::read_dir(dir)
+        .ok()?
+        .flatten()
+        .filter(|e| !e.file_name().to_string_lossy().starts_with('.'))
+        .filter_map(|e| File::new(&e.path(), e.metadata().ok()).ok())
+        .collect::<Vec<_>>();
+    let explicit = files
+        .iter()
+        .filter_map(|f| cover_rank(f.name()).map(|rank| (rank, f)))
+        .min_by_key(|(rank, _)| *rank);
+    if let Some((_, file)) = explicit {
+        return Some(dir.join(file.name()));
+    }
+
+    files.sort_unstable_by(|a, b| a.cmp_by(b, order, reverse));
+    if let Some(file) = files.iter().find(|f| f.is_decodable_image()) {
+        return Some(dir.join(file.name()));
+    }
+    files
+        .iter()
+        .filter(|f| f.is_dir())
+        .find_map(|f| search(&dir.join(f.name()), order, reverse, depth + 1, dirs))
+}
+
+fn cover_rank(name: &str) -> Option<usize> {
+    let name = name.to_ascii_lowercase();
+    let (stem, extension) = name.rsplit_once('.')?;
+    if !COVER_EXTENSIONS.contains(&extension) {
+        return None;
+    }
+    COVER_STEMS.iter().position(|s| *s == stem)
+}
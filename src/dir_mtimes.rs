@@ -0,0 +1,39 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::{Instant, SystemTime},
+};
+
+// The directories that a cached result was read from. Adding or removing a file changes
+// the mtime of its directory, so the result is fresh while none of them changed.
+#[derive(Default)]
+pub(crate) struct DirMtimes(Vec<(PathBuf, SystemTime)>);
+
+impl DirMtimes {
+    // `None` when the mtime of `dir` cannot be read.
+    pub fn record(&mut self, dir: &Path) -> Option<()> {
+        self.0.push((dir.to_owned(), modified_at(dir)?));
+        Some(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    // `None` when `deadline` passes before every directory is checked.
+    pub fn is_fresh(&self, deadline: Option<Instant>) -> Option<bool> {
+        for (dir, modified) in &self.0 {
+            if deadline.is_some_and(|d| Instant::now() > d) {
+                return None;
+            }
+            if modified_at(dir).as_ref() != Some(modified) {
+                return Some(false);
+            }
+        }
+        Some(true)
+    }
+}
+
+fn modified_at(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
@@ -0,0 +1,143 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, OnceLock},
+    time::Instant,
+};
+
+use crate::{dir_mtimes::DirMtimes, model::MediaType};
+
+const SIZE_UNITS: &[&str] = &["B", "KiB", "MiB", "GiB", "TiB"];
+
+#[derive(Clone)]
+pub(crate) struct DirStats {
+    counts: BTreeMap<&'static str, usize>,
+    size: Option<u64>,
+}
+
+struct Cached {
+    dirs: DirMtimes,
+    stats: DirStats,
+}
+
+type StatsCache = Mutex<HashMap<(PathBuf, bool), Arc<Cached>>>;
+
+static CACHE: OnceLock<StatsCache> = OnceLock::new();
+
+impl DirStats {
+    pub fn counts(&self) -> &BTreeMap<&'static str, usize> {
+        &self.counts
+    }
+
+    pub fn size(&self) -> Option<u64> {
+        self.size
+    }
+
+    pub fn summary(&self) -> String {
+        let mut counts = self.counts.iter().collect::<Vec<_>>();
+        counts.sort_by(|a, b| b.1.cmp(a.1));
+        let mut parts = counts
+            .into_iter()
+            .map(|(name, count)| format!("{} {}", name, count))
+            .collect::<Vec<_>>();
+        if parts.is_empty() {
+            parts.push("empty".to_owned());
+        }
+        if let Some(size) = self.size {
+            parts.push(format_size(size));
+        }
+        parts.join(" \u{b7} ")
+    }
+}
+
+// Counts the direct children by media type, and adds up the size of the whole subtree
+// with `recursive`. Gives up when `deadline` passes, rather than caching partial stats.
+pub(crate) fn compute(dir: &Path, recursive: bool, deadline: Instant) -> Option<DirStats> {
+    let key = (dir.to_owned(), recursive);
+    let cache = CACHE.get_or_init(Default::default);
+    let cached = cache.lock().unwrap().get(&key).cloned();
+    if let Some(cached) = cached {
+        if cached.dirs.is_fresh(Some(deadline))? {
+            return Some(cached.stats.clone());
+        }
+    }
+
+    let mut dirs = DirMtimes::default();
+    let counts = count(dir, deadline, &mut dirs)?;
+    let size = if recursive {
+        Some(total_size(dir, deadline, &mut dirs)?)
+    } else {
+        None
+    };
+    let stats = DirStats { counts, size };
+    cache.lock().unwrap().insert(
+        key,
+        Arc::new(Cached {
+            dirs,
+            stats: stats.clone(),
+        }),
+    );
+    Some(stats)
+}
+
+fn count(
+    dir: &Path,
+    deadline: Instant,
+    dirs: &mut DirMtimes,
+) -> Option<BTreeMap<&'static str, usize>> {
+    dirs.record(dir)?;
+    let mut counts = BTreeMap::new();
+    for entry in fs::read_dir(dir).ok()?.flatten() {
+        if Instant::now() > deadline {
+            return None;
+        }
+        let path = entry.path();
+        let name = if path.is_dir() {
+            "directory"
+        } else {
+            MediaType::new(&path).name()
+        };
+        *counts.entry(name).or_insert(0) += 1;
+    }
+    Some(counts)
+}
+
+// Symbolic links are not followed, so that nothing is counted twice.
+fn total_size(dir: &Path, deadline: Instant, dirs: &mut DirMtimes) -> Option<u64> {
+    let mut size = 0;
+    let mut targets = vec![dir.to_owned()];
+    while let Some(target) = targets.pop() {
+        if Instant::now() > deadline {
+            return None;
+        }
+        if target != dir {
+            dirs.record(&target)?;
+        }
+        let Ok(entries) = fs::read_dir(&target) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            match entry.metadata() {
+                Ok(m) if m.is_dir() => targets.push(entry.path()),
+                Ok(m) if m.is_file() => size += m.len(),
+                _ => {}
+            }
+        }
+    }
+    Some(size)
+}
+
+fn format_size(size: u64) -> String {
+    let mut value = size as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < SIZE_UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} {}", size, SIZE_UNITS[unit])
+    } else {
+        format!("{:.1} {}", value, SIZE_UNITS[unit])
+    }
+}
@@ -0,0 +1,525 @@
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap},
+    fs::{self, DirEntry},
+    hash::{Hash, Hasher},
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        OnceLock, RwLock,
+    },
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{anyhow, Result};
+use image::{imageops::FilterType, GrayImage};
+use rand::Rng;
+use rayon::prelude::*;
+
+use crate::{convert, model::File};
+
+// Beyond this many of the 64 bits, unrelated images start to match.
+const MAX_THRESHOLD: u32 = 16;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) enum HashAlgorithm {
+    Average,
+    Difference,
+    Perceptual,
+}
+
+pub(crate) struct Duplicates {
+    pub groups: Vec<Vec<File>>,
+    // Whether the time ran out before every image was hashed.
+    pub exhausted: bool,
+}
+
+type Index = HashMap<(PathBuf, HashAlgorithm), (SystemTime, Option<u64>)>;
+type HashIndex = RwLock<Index>;
+
+static INDEX: OnceLock<HashIndex> = OnceLock::new();
+
+// A BK-tree over the Hamming distances between hashes, so that finding the neighbours of a
+// hash does not compare it with every other one.
+#[derive(Default)]
+struct BkTree {
+    nodes: Vec<BkNode>,
+}
+
+struct BkNode {
+    hash: u64,
+    index: usize,
+    children: Vec<(u32, usize)>,
+}
+
+impl FromStr for HashAlgorithm {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "ahash" => Ok(Self::Average),
+            "dhash" => Ok(Self::Difference),
+            "phash" => Ok(Self::Perceptual),
+            _ => Err(format!("Invalid hash algorithm: {}", s)),
+        }
+    }
+}
+
+impl HashAlgorithm {
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Average => "ahash",
+            Self::Difference => "dhash",
+            Self::Perceptual => "phash",
+        }
+    }
+
+    fn compute(&self, path: &Path) -> Result<u64> {
+        let image = convert::decode(path)?;
+        let hash = match self {
+            Self::Average => average_hash(&resize(&image, 8, 8)),
+            Self::Difference => difference_hash(&resize(&image, 9, 8)),
+            Self::Perceptual => perceptual_hash(&resize(&image, 32, 32)),
+        };
+        Ok(hash)
+    }
+}
+
+impl BkTree {
+    fn insert(&mut self, hash: u64, index: usize) {
+        let new = self.nodes.len();
+        self.nodes.push(BkNode {
+            hash,
+            index,
+            children: vec![],
+        });
+        if new == 0 {
+            return;
+        }
+
+        let mut current = 0;
+        loop {
+            let distance = (self.nodes[current].hash ^ hash).count_ones();
+            match self.nodes[current]
+                .children
+                .iter()
+                .find(|(d, _)| *d == distance)
+            {
+                Some(&(_, child)) => current = child,
+                None => {
+                    self.nodes[current].children.push((distance, new));
+                    return;
+                }
+            }
+        }
+    }
+
+    fn find(&self, hash: u64, threshold: u32) -> Vec<usize> {
+        let mut found = vec![];
+        let mut stack = if self.nodes.is_empty() {
+            vec![]
+        } else {
+            vec![0]
+        };
+        while let Some(current) = stack.pop() {
+            let node = &self.nodes[current];
+            let distance = (node.hash ^ hash).count_ones();
+            if distance <= threshold {
+                found.push(node.index);
+            }
+            stack.extend(
+                node.children
+                    .iter()
+                    .filter(|(d, _)| d.abs_diff(distance) <= threshold)
+                    .map(|(_, child)| child),
+            );
+        }
+        found
+    }
+}
+
+// Only the entries that pass `filter` are looked at, like in listings. Once `deadline` passes,
+// only the images that were hashed before are compared, and the hashes computed so far are
+// kept for the next time.
+pub(crate) fn find_duplicates(
+    root: &Path,
+    cache_dir: &Path,
+    algorithm: HashAlgorithm,
+    threshold: u32,
+    filter: impl Fn(&DirEntry) -> bool,
+    deadline: Instant,
+) -> Result<Duplicates> {
+    let threshold = threshold.min(MAX_THRESHOLD);
+    let mut paths = vec![];
+    let skipped_dir = cache_dir.canonicalize().ok();
+    let exhausted = AtomicBool::new(!collect_images(
+        root,
+        &skipped_dir,
+        &filter,
+        deadline,
+        &mut paths,
+    ));
+
+    let index_path = index_path(root, cache_dir)?;
+    let index = INDEX.get_or_init(|| RwLock::new(load_index(&index_path)));
+    let updated = AtomicBool::new(false);
+    let hashed = paths
+        .into_par_iter()
+        .filter_map(|path| {
+            let hash = hash(index, &updated, &exhausted, &path, algorithm, deadline)?;
+            Some((hash, path))
+        })
+        .collect::<Vec<_>>();
+    if updated.load(Ordering::Relaxed) {
+        if let Err(e) = save_index(&index_path, &index.read().unwrap()) {
+            tracing::warn!("Failed to save {}: {:?}", index_path.display(), e);
+        }
+    }
+
+    let mut tree = BkTree::default();
+    for (i, (hash, _)) in hashed.iter().enumerate() {
+        tree.insert(*hash, i);
+    }
+    let edges = (0..hashed.len())
+        .into_par_iter()
+        .flat_map_iter(|i| {
+            tree.find(hashed[i].0, threshold)
+                .into_iter()
+                .filter(move |&j| j > i)
+                .map(move |j| (i, j))
+        })
+        .collect::<Vec<_>>();
+
+    let mut parents = (0..hashed.len()).collect::<Vec<_>>();
+    for (i, j) in edges {
+        let (i, j) = (find_root(&mut parents, i), find_root(&mut parents, j));
+        parents[i.max(j)] = i.min(j);
+    }
+
+    let mut groups: HashMap<usize, Vec<File>> = HashMap::new();
+    for (i, (_, path)) in hashed.iter().enumerate() {
+        let root_index = find_root(&mut parents, i);
+        let name = path.strip_prefix(root).unwrap_or(path).to_string_lossy();
+        let file = File::new_with_name(path, name, fs::metadata(path).ok())?;
+        groups.entry(root_index).or_default().push(file);
+    }
+
+    let mut groups = groups
+        .into_values()
+        .filter(|g| g.len() > 1)
+        .collect::<Vec<_>>();
+    for group in groups.iter_mut() {
+        group.sort_unstable_by(|a, b| a.name().cmp(b.name()));
+    }
+    groups.sort_unstable_by(|a, b| a[0].name().cmp(b[0].name()));
+    Ok(Duplicates {
+        groups,
+        exhausted: exhausted.into_inner(),
+    })
+}
+
+// Unreadable entries are skipped, so that they do not hide the duplicates elsewhere. Hidden
+// entries and `skipped_dir`, the cache directory, are left out. Returns false when `deadline`
+// passes before the walk ends.
+fn collect_images(
+    dir: &Path,
+    skipped_dir: &Option<PathBuf>,
+    filter: &impl Fn(&DirEntry) -> bool,
+    deadline: Instant,
+    paths: &mut Vec<PathBuf>,
+) -> bool {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            tracing::warn!("Failed to read {}: {:?}", dir.display(), e);
+            return true;
+        }
+    };
+    for entry in entries {
+        if Instant::now() > deadline {
+            return false;
+        }
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => {
+                tracing::warn!("Failed to read an entry in {}: {:?}", dir.display(), e);
+                continue;
+            }
+        };
+        if entry.file_name().to_string_lossy().starts_with('.') || !filter(&entry) {
+            continue;
+        }
+        let path = entry.path();
+        match entry.file_type() {
+            // Symlinked directories are not followed to avoid cycles.
+            Ok(t) if t.is_dir() => {
+                if skipped_dir.is_some() && path.canonicalize().ok() == *skipped_dir {
+                    continue;
+                }
+                if !collect_images(&path, skipped_dir, filter, deadline, paths) {
+                    return false;
+                }
+            }
+            Ok(_) if File::new(&path, None).is_ok_and(|f| f.is_decodable_image()) => {
+                paths.push(path)
+            }
+            Ok(_) => {}
+            Err(e) => tracing::warn!("Failed to read {}: {:?}", path.display(), e),
+        }
+    }
+    true
+}
+
+// Images that are not hashed yet when `deadline` has passed are left out, and set `exhausted`.
+fn hash(
+    index: &HashIndex,
+    updated: &AtomicBool,
+    exhausted: &AtomicBool,
+    path: &Path,
+    algorithm: HashAlgorithm,
+    deadline: Instant,
+) -> Option<u64> {
+    let modified = fs::metadata(path).and_then(|m| m.modified()).ok()?;
+    let key = (path.to_owned(), algorithm);
+
+    if let Some((cached_at, hash)) = index.read().unwrap().get(&key) {
+        if *cached_at == modified {
+            return *hash;
+        }
+    }
+    if Instant::now() > deadline {
+        exhausted.store(true, Ordering::Relaxed);
+        return None;
+    }
+
+    let hash = match algorithm.compute(path) {
+        Ok(hash) => Some(hash),
+        Err(e) => {
+            tracing::trace!("Failed to hash {}: {}", path.display(), e);
+            None
+        }
+    };
+    index.write().unwrap().insert(key, (modified, hash));
+    updated.store(true, Ordering::Relaxed);
+    hash
+}
+
+// The index is kept in the cache directory, one file per document root, so that a restart
+// does not decode every image again.
+fn index_path(root: &Path, cache_dir: &Path) -> Result<PathBuf> {
+    let mut hasher = DefaultHasher::new();
+    root.canonicalize()?.hash(&mut hasher);
+    Ok(cache_dir.join(format!("duplicates-{:016x}.index", hasher.finish())))
+}
+
+// Each line holds the algorithm, the modification time, the hash or `-` for images that
+// failed to decode, and the path, separated by tabs.
+fn load_index(index_path: &Path) -> Index {
+    let Ok(content) = fs::read_to_string(index_path) else {
+        return HashMap::new();
+    };
+    let index = content
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.splitn(4, '\t');
+            let algorithm = fields.next()?.parse().ok()?;
+            let (secs, nanos) = fields.next()?.split_once('.')?;
+            let modified = UNIX_EPOCH + Duration::new(secs.parse().ok()?, nanos.parse().ok()?);
+            let hash = match fields.next()? {
+                "-" => None,
+                hash => Some(u64::from_str_radix(hash, 16).ok()?),
+            };
+            let path = PathBuf::from(fields.next()?);
+            Some(((path, algorithm), (modified, hash)))
+        })
+        .collect::<Index>();
+    tracing::debug!(
+        "loaded {} hashes from {}",
+        index.len(),
+        index_path.display()
+    );
+    index
+}
+
+fn save_index(index_path: &Path, index: &Index) -> Result<()> {
+    let mut content = String::new();
+    for ((path, algorithm), (modified, hash)) in index {
+        // Images that were removed since are dropped.
+        let Some(path) = path
+            .to_str()
+            .filter(|p| !p.contains('\n') && Path::new(p).exists())
+        else {
+            continue;
+        };
+        let modified = modified
+            .duration_since(UNIX_EPOCH)
+            .map_err(|e| anyhow!(e))?;
+        let hash = hash.map_or_else(|| "-".to_owned(), |h| format!("{:016x}", h));
+        content.push_str(&format!(
+            "{}\t{}.{:09}\t{}\t{}\n",
+            algorithm.name(),
+            modified.as_secs(),
+            modified.subsec_nanos(),
+            hash,
+            path
+        ));
+    }
+
+    let tmp_path =
+        index_path.with_extension(format!("{:016x}.tmp", rand::thread_rng().gen::<u64>()));
+    fs::write(&tmp_path, content)?;
+    if let Err(e) = fs::rename(&tmp_path, index_path) {
+        let _ = fs::remove_file(&tmp_path);
+        return Err(e.into());
+    }
+    Ok(())
+}
+
+fn find_root(parents: &mut [usize], mut i: usize) -> usize {
+    while parents[i] != i {
+        parents[i] = parents[parents[i]];
+        i = parents[i];
+    }
+    i
+}
+
+fn resize(image: &image::DynamicImage, width: u32, height: u32) -> GrayImage {
+    image
+        .resize_exact(width, height, FilterType::Triangle)
+        .to_luma8()
+}
+
+fn bits(values: impl Iterator<Item = bool>) -> u64 {
+    values.fold(0, |hash, bit| (hash << 1) | u64::from(bit))
+}
+
+fn average_hash(image: &GrayImage) -> u64 {
+    let pixels = image.as_raw();
+    let mean = pixels.iter().map(|&p| u32::from(p)).sum::<u32>() / pixels.len() as u32;
+    bits(pixels.iter().map(|&p| u32::from(p) > mean))
+}
+
+fn difference_hash(image: &GrayImage) -> u64 {
+    bits((0..image.height()).flat_map(|y| {
+        (0..image.width() - 1).map(move |x| image.get_pixel(x, y)[0] < image.get_pixel(x + 1, y)[0])
+    }))
+}
+
+// The low frequencies of a 32x32 DCT, compared against their median.
+fn perceptual_hash(image: &GrayImage) -> u64 {
+    const SIZE: usize = 32;
+    const LOW: usize = 8;
+
+    let cosines = (0..LOW)
+        .map(|u| {
+            (0..SIZE)
+                .map(|x| {
+                    ((2 * x + 1) as f64 * u as f64 * std::f64::consts::PI / (2 * SIZE) as f64).cos()
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+
+    let pixels = image.as_raw();
+    let rows = (0..SIZE)
+        .map(|y| {
+            (0..LOW)
+                .map(|u| {
+                    (0..SIZE)
+                        .map(|x| f64::from(pixels[y * SIZE + x]) * cosines[u][x])
+                        .sum::<f64>()
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+    let coefficients = (0..LOW)
+        .flat_map(|v| (0..LOW).map(move |u| (u, v)))
+        .map(|(u, v)| (0..SIZE).map(|y| rows[y][u] * cosines[v][y]).sum::<f64>())
+        .collect::<Vec<_>>();
+
+    // The DC term only reflects overall brightness.
+    let mut sorted = coefficients[1..].to_vec();
+    sorted.sort_unstable_by(f64::total_cmp);
+    let median = sorted[sorted.len() / 2];
+    bits(coefficients.iter().map(|&c| c > median))
+}
+
+#[cfg(test)]
+mod tests {
+    use image::Luma;
+
+    use super::*;
+
+    fn distance(a: u64, b: u64) -> u32 {
+        (a ^ b).count_ones()
+    }
+
+    // A gradient with some texture, so that the hashes have bits of both values.
+    fn image(size: u32, shift: u8) -> GrayImage {
+        GrayImage::from_fn(size, size, |x, y| {
+            let value = (x * 7 + y * 3 + (x * y) % 13) % 200;
+            Luma([value as u8 + shift])
+        })
+    }
+
+    #[test]
+    fn finds_hashes_within_the_threshold() {
+        // A linear congruential generator keeps the hashes the same on every run.
+        let mut state = 1u64;
+        let hashes = (0..500)
+            .map(|_| {
+                state = state
+                    .wrapping_mul(6364136223846793005)
+                    .wrapping_add(1442695040888963407);
+                state
+            })
+            .collect::<Vec<_>>();
+        let mut tree = BkTree::default();
+        for (i, hash) in hashes.iter().enumerate() {
+            tree.insert(*hash, i);
+        }
+
+        for threshold in [0, 24, 32] {
+            for &hash in hashes.iter().take(20) {
+                let mut found = tree.find(hash, threshold);
+                found.sort_unstable();
+                let expected = (0..hashes.len())
+                    .filter(|&i| distance(hashes[i], hash) <= threshold)
+                    .collect::<Vec<_>>();
+                assert_eq!(found, expected);
+            }
+        }
+        assert!(BkTree::default().find(0, 64).is_empty());
+    }
+
+    #[test]
+    fn hashes_flat_images_and_gradients() {
+        assert_eq!(average_hash(&GrayImage::from_pixel(8, 8, Luma([128]))), 0);
+        let gradient = GrayImage::from_fn(9, 8, |x, _| Luma([x as u8 * 10]));
+        assert_eq!(difference_hash(&gradient), u64::MAX);
+    }
+
+    #[test]
+    fn hashes_similar_images_alike() {
+        let (a, b) = (image(32, 0), image(32, 20));
+        let inverted = GrayImage::from_fn(32, 32, |x, y| Luma([255 - a.get_pixel(x, y)[0]]));
+        assert_eq!(perceptual_hash(&a), perceptual_hash(&b));
+        assert!(distance(perceptual_hash(&a), perceptual_hash(&inverted)) > 32);
+
+        let (a, b) = (image(8, 0), image(8, 20));
+        assert_eq!(average_hash(&a), average_hash(&b));
+    }
+
+    #[test]
+    fn parses_algorithm_names() {
+        for algorithm in [
+            HashAlgorithm::Average,
+            HashAlgorithm::Difference,
+            HashAlgorithm::Perceptual,
+        ] {
+            assert!(algorithm.name().parse::<HashAlgorithm>() == Ok(algorithm));
+        }
+        assert!("PHASH".parse::<HashAlgorithm>() == Ok(HashAlgorithm::Perceptual));
+        assert!("md5".parse::<HashAlgorithm>().is_err());
+    }
+}
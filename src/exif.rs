@@ -0,0 +1,208 @@
+use std::{
+    fs,
+    io::BufReader,
+    path::Path,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::Result;
+use exif::{DateTime, Field, In, Reader, Tag, Value};
+
+const EXIF_EXTENSIONS: &[&str] = &[
+    "jpg", "jpeg", "jfif", "pjpeg", "pjp", "tif", "tiff", "heif", "heic", "avif", "webp", "png",
+    "dng", "cr2", "nef", "nrw", "arw", "pef", "srw",
+];
+
+#[derive(Clone, Default)]
+pub(crate) struct Exif {
+    taken_at: Option<SystemTime>,
+    taken_at_text: Option<String>,
+    camera: Option<String>,
+    lens: Option<String>,
+    exposure: Option<String>,
+    width: Option<u32>,
+    height: Option<u32>,
+    orientation: Option<u32>,
+}
+
+impl Exif {
+    pub fn read(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_string_lossy().to_ascii_lowercase();
+        if !EXIF_EXTENSIONS.contains(&extension.as_str()) {
+            return None;
+        }
+
+        match Self::read_from_file(path) {
+            Ok(exif) => Some(exif),
+            Err(e) => {
+                tracing::trace!("Failed to read EXIF of {}: {}", path.display(), e);
+                None
+            }
+        }
+    }
+
+    fn read_from_file(path: &Path) -> Result<Self> {
+        let file = fs::File::open(path)?;
+        let exif = Reader::new().read_from_container(&mut BufReader::new(file))?;
+        let field = |tag| exif.get_field(tag, In::PRIMARY);
+
+        let taken_at = field(Tag::DateTimeOriginal)
+            .or_else(|| field(Tag::DateTime))
+            .and_then(|f| parse_date_time(f, field(Tag::OffsetTimeOriginal)));
+
+        let camera = match (
+            field(Tag::Make).and_then(ascii),
+            field(Tag::Model).and_then(ascii),
+        ) {
+            (Some(make), Some(model)) if model.starts_with(&make) => Some(model),
+            (Some(make), Some(model)) => Some(format!("{} {}", make, model)),
+            (make, model) => make.or(model),
+        };
+
+        let exposure = [
+            Tag::ExposureTime,
+            Tag::FNumber,
+            Tag::PhotographicSensitivity,
+            Tag::FocalLength,
+        ]
+        .iter()
+        .filter_map(|&tag| field(tag))
+        .map(|f| match f.tag {
+            Tag::PhotographicSensitivity => format!("ISO {}", f.display_value()),
+            _ => f.display_value().with_unit(&exif).to_string(),
+        })
+        .collect::<Vec<_>>();
+
+        let dimension = |tags: &[Tag]| {
+            tags.iter()
+                .filter_map(|&tag| field(tag))
+                .find_map(|f| f.value.get_uint(0))
+        };
+
+        Ok(Self {
+            taken_at: taken_at.as_ref().and_then(to_system_time),
+            taken_at_text: taken_at.map(|d| d.to_string()),
+            camera,
+            lens: field(Tag::LensModel).and_then(ascii),
+            exposure: (!exposure.is_empty()).then(|| exposure.join(" ")),
+            width: dimension(&[Tag::PixelXDimension, Tag::ImageWidth]),
+            height: dimension(&[Tag::PixelYDimension, Tag::ImageLength]),
+            orientation: field(Tag::Orientation).and_then(|f| f.value.get_uint(0)),
+        })
+    }
+
+    pub fn taken_at(&self) -> Option<SystemTime> {
+        self.taken_at
+    }
+
+    pub fn dimensions(&self) -> Option<(u32, u32)> {
+        let (width, height) = (self.width?, self.height?);
+        if self.is_transposed() {
+            Some((height, width))
+        } else {
+            Some((width, height))
+        }
+    }
+
+    // Orientations 5 to 8 rotate the image by 90 or 270 degrees.
+    pub fn is_transposed(&self) -> bool {
+        matches!(self.orientation, Some(5..=8))
+    }
+
+    pub fn summary(&self) -> Vec<String> {
+        let dimensions = self.dimensions().map(|(w, h)| format!("{} x {}", w, h));
+
+        [
+            &self.taken_at_text,
+            &self.camera,
+            &self.lens,
+            &self.exposure,
+            &dimensions,
+        ]
+        .into_iter()
+        .flatten()
+        .cloned()
+        .collect()
+    }
+}
+
+fn ascii(field: &Field) -> Option<String> {
+    match &field.value {
+        Value::Ascii(values) => values
+            .first()
+            .map(|v| String::from_utf8_lossy(v).trim().to_owned())
+            .filter(|v| !v.is_empty()),
+        _ => None,
+    }
+}
+
+fn parse_date_time(field: &Field, offset: Option<&Field>) -> Option<DateTime> {
+    let mut date_time = match &field.value {
+        Value::Ascii(values) => DateTime::from_ascii(values.first()?).ok()?,
+        _ => return None,
+    };
+
+    if let Some(Value::Ascii(values)) = offset.map(|o| &o.value) {
+        if let Some(value) = values.first() {
+            let _ = date_time.parse_offset(value);
+        }
+    }
+    Some(date_time)
+}
+
+fn to_system_time(date_time: &DateTime) -> Option<SystemTime> {
+    let days = days_from_civil(
+        date_time.year.into(),
+        date_time.month.into(),
+        date_time.day.into(),
+    )?;
+    let seconds = days * 86400
+        + i64::from(date_time.hour) * 3600
+        + i64::from(date_time.minute) * 60
+        + i64::from(date_time.second)
+        - i64::from(date_time.offset.unwrap_or(0)) * 60;
+
+    let seconds = u64::try_from(seconds).ok()?;
+    Some(UNIX_EPOCH + Duration::from_secs(seconds))
+}
+
+// Days since 1970-01-01 in the proleptic Gregorian calendar.
+fn days_from_civil(year: i64, month: i64, day: i64) -> Option<i64> {
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    Some(era * 146097 + day_of_era - 719468)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_days_from_the_epoch() {
+        assert_eq!(days_from_civil(1970, 1, 1), Some(0));
+        assert_eq!(days_from_civil(1969, 12, 31), Some(-1));
+        assert_eq!(days_from_civil(2000, 3, 1), Some(11017));
+        assert_eq!(days_from_civil(2024, 2, 29), Some(19782));
+        assert_eq!(days_from_civil(1600, 1, 1), Some(-135140));
+        assert_eq!(days_from_civil(2024, 13, 1), None);
+        assert_eq!(days_from_civil(2024, 1, 0), None);
+    }
+
+    #[test]
+    fn converts_local_times_with_offsets() {
+        let mut date_time = DateTime::from_ascii(b"2024:02:29 12:34:56").unwrap();
+        let local = to_system_time(&date_time).unwrap();
+        assert_eq!(local, UNIX_EPOCH + Duration::from_secs(1709210096));
+
+        date_time.parse_offset(b"+09:00").unwrap();
+        let utc = to_system_time(&date_time).unwrap();
+        assert_eq!(utc, UNIX_EPOCH + Duration::from_secs(1709177696));
+    }
+}
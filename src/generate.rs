@@ -21,7 +21,10 @@ pub fn generate_static_pages(config: Arc<Config>, add_watermark: bool) -> Result
     loop {
         let mut child_containers_vec = vec![];
         for target_uri in &next_targets {
-            let containers = handle::walk_dir(target_uri, &HashMap::new(), &config)
+            // Static pages cannot be paged through with query parameters.
+            let query = HashMap::from([("per_page".to_owned(), "0".to_owned())]);
+            let config = config.for_dir(target_uri);
+            let containers = handle::walk_dir(target_uri, &query, &config, true)
                 .map_err(|e| e.into_internal())?;
             child_containers_vec.push(containers.clone());
 
@@ -30,6 +33,9 @@ pub fn generate_static_pages(config: Arc<Config>, add_watermark: bool) -> Result
                 "",
                 containers,
                 config.lazy(),
+                true,
+                false,
+                false,
                 config.title_prefix(),
                 true,
                 add_watermark,
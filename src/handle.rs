@@ -1,31 +1,223 @@
-use std::{collections::HashMap, fs::DirEntry, path::Path, sync::Arc};
+use std::{
+    collections::HashMap,
+    fs::DirEntry,
+    path::{Component, Path, PathBuf},
+    str::FromStr,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 use anyhow::{anyhow, Result};
+use askama::Template;
 use axum::{
     body::Body,
     extract::{Query, RawQuery},
-    http::{header::CACHE_CONTROL, Request, StatusCode, Uri},
+    http::{
+        header::{
+            ACCEPT, CACHE_CONTROL, CONTENT_SECURITY_POLICY, CONTENT_TYPE, X_CONTENT_TYPE_OPTIONS,
+        },
+        Request, StatusCode, Uri,
+    },
     response::{IntoResponse, Response},
     routing::get,
-    Extension, Router,
+    Extension, Json, Router,
 };
 use hyper::HeaderMap;
 use rayon::prelude::*;
 use regex::{Regex, RegexBuilder};
 use tower::ServiceExt;
-use tower_http::services::ServeDir;
+use tower_http::services::{ServeDir, ServeFile};
 
 use crate::{
-    config::Config,
+    api::{DirTree, Listing, Playlist},
+    cache,
+    config::{Config, DIR_CONFIG_FILE_NAME},
+    convert::{self, OutputFormat},
+    dir_cover,
+    duplicates::{self, HashAlgorithm},
     error::AppError,
-    model::{File, FilesContainer, SortOrder},
-    template::{HtmlTemplate, RatticeTemplate},
+    model::{self, File, FilesContainer, ListingParams, SortOrder},
+    preview, raw, search, sniff, subtitle, svg, tags,
+    template::{
+        HtmlTemplate, MarkdownTemplate, PagePart, RatticeTemplate, SlideshowTemplate,
+        ViewerTemplate,
+    },
+    thumbnail,
 };
 
 pub const REGEX_SIZE_LIMIT: usize = 1024 * 1024;
+const SEARCH_CHANNEL_CAPACITY: usize = 16;
+const DIR_STATS_TIME_LIMIT: Duration = Duration::from_secs(2);
+const DUPLICATES_TIME_LIMIT: Duration = Duration::from_secs(20);
+
+// In seconds.
+const SLIDESHOW_INTERVAL: f64 = 10.0;
+const SLIDESHOW_FADE: f64 = 1.0;
+const SLIDESHOW_REFRESH: f64 = 300.0;
+const SLIDESHOW_PARAMS: &[&str] = &[
+    "slideshow",
+    "interval",
+    "fade",
+    "refresh",
+    "shuffle",
+    "page",
+    "search",
+    "view",
+];
 
 pub fn add_handler(app: Router) -> Router {
-    app.nest_service("/", get(handle_request))
+    app.route("/_rattice/duplicates", get(handle_duplicates))
+        .route("/_rattice/api/list", get(handle_api_list))
+        .route("/_rattice/api/list/", get(handle_api_list))
+        .route("/_rattice/api/list/*path", get(handle_api_list))
+        .route("/_rattice/api/dirs", get(handle_api_dirs))
+        .route("/_rattice/api/dirs/", get(handle_api_dirs))
+        .route("/_rattice/api/dirs/*path", get(handle_api_dirs))
+        .route("/_rattice/api/slideshow", get(handle_api_slideshow))
+        .route("/_rattice/api/slideshow/", get(handle_api_slideshow))
+        .route("/_rattice/api/slideshow/*path", get(handle_api_slideshow))
+        .nest_service("/", get(handle_request))
+}
+
+async fn handle_api_list(
+    uri: Uri,
+    Query(mut query): Query<HashMap<String, String>>,
+    Extension(config): Extension<Arc<Config>>,
+) -> Result<Response, AppError> {
+    let uri = api_target(&uri, "/_rattice/api/list")?;
+
+    if config.ignore_query_params() {
+        query.clear();
+    }
+    serve_json(&uri, &query, &config)
+}
+
+// Lists only the subdirectories, for the tree in the sidebar.
+async fn handle_api_dirs(
+    uri: Uri,
+    Extension(config): Extension<Arc<Config>>,
+) -> Result<Response, AppError> {
+    let uri = api_target(&uri, "/_rattice/api/dirs")?;
+
+    local_path(&uri)?;
+    let decoded_uri = percent_encoding::percent_decode_str(uri.path()).decode_utf8_lossy();
+    let config = config.for_dir(&decoded_uri);
+    let re_dir = extract_regex("filter_dir", &HashMap::new(), || {
+        config.filter_dir_pattern()
+    })?;
+    let entries =
+        std::fs::read_dir(format!(".{}", decoded_uri)).map_err(|e| AppError::NotFound(e.into()))?;
+    let mut dirs = entries
+        .flatten()
+        .filter(|e| e.path().is_dir() && filter_entry(e, &re_dir, &None))
+        .map(|e| File::new(&e.path(), e.metadata().ok()))
+        .collect::<Result<Vec<_>>>()?;
+    dirs.sort_unstable_by(|a, b| a.cmp_by(b, config.sort_order(), config.reverse()));
+    Ok(Json(DirTree::new(&decoded_uri, &dirs)).into_response())
+}
+
+// Lists the images and videos under the directory, down to `depth` levels, for the slideshow.
+async fn handle_api_slideshow(
+    uri: Uri,
+    Query(mut query): Query<HashMap<String, String>>,
+    Extension(config): Extension<Arc<Config>>,
+) -> Result<Response, AppError> {
+    let uri = api_target(&uri, "/_rattice/api/slideshow")?;
+    local_path(&uri)?;
+    let decoded_uri = percent_encoding::percent_decode_str(uri.path()).decode_utf8_lossy();
+    let config = config.for_dir(&decoded_uri);
+    if config.ignore_query_params() {
+        query.clear();
+    }
+    query.insert("per_page".to_owned(), "0".to_owned());
+
+    let containers = walk_dir(&decoded_uri, &query, &config, false)?;
+    let mut files = containers
+        .iter()
+        .flat_map(|c| c.files())
+        .filter(|f| f.is_image() || f.is_raw() || f.is_video())
+        .collect::<Vec<_>>();
+    // Each directory is shuffled on its own, so the playlist is shuffled again as a whole.
+    // Paths rather than names are hashed, as names repeat across directories.
+    let order = containers
+        .first()
+        .and_then(|c| c.params())
+        .map(|p| &p.order);
+    if let Some(&SortOrder::Random(seed)) = order {
+        files.sort_by_cached_key(|f| (model::shuffle_key(f.path(), seed), f.path().to_owned()));
+    }
+    Ok(Json(Playlist::new(&decoded_uri, &files)).into_response())
+}
+
+async fn handle_duplicates(
+    uri: Uri,
+    Query(mut query): Query<HashMap<String, String>>,
+    RawQuery(mut raw_query): RawQuery,
+    Extension(config): Extension<Arc<Config>>,
+) -> Result<Response, AppError> {
+    if config.ignore_query_params() {
+        query.clear();
+        raw_query.take();
+    }
+    let algorithm: HashAlgorithm = query
+        .get("hash")
+        .map(|h| h.parse())
+        .transpose()
+        .map_err(|e: String| AppError::BadRequest(anyhow!(e)))?
+        .unwrap_or(HashAlgorithm::Difference);
+    let threshold = query_param(&query, "threshold", || config.duplicate_threshold());
+
+    let re_dir = extract_regex("filter_dir", &query, || config.filter_dir_pattern())?;
+    let re_file = extract_regex("filter_file", &query, || config.filter_file_pattern())?;
+
+    let cache_dir = config.cache_dir().to_owned();
+    let deadline = Instant::now() + DUPLICATES_TIME_LIMIT;
+    let duplicates = tokio::task::spawn_blocking(move || {
+        duplicates::find_duplicates(
+            Path::new("."),
+            &cache_dir,
+            algorithm,
+            threshold,
+            |e| filter_entry(e, &re_dir, &re_file),
+            deadline,
+        )
+    })
+    .await
+    .map_err(|e| anyhow!(e))??;
+    let containers = duplicates
+        .groups
+        .into_iter()
+        .enumerate()
+        .map(|(i, files)| {
+            FilesContainer::new(format!("Group {} ({} images)", i + 1, files.len()), files)
+        })
+        .collect();
+
+    let raw_query = raw_query.map(|r| format!("?{}", r)).unwrap_or_default();
+    let lazy = query_param(&query, "lazy", || config.lazy());
+
+    let template = RatticeTemplate::new(
+        uri.path(),
+        &raw_query,
+        containers,
+        lazy,
+        config.animate(),
+        config.thumbnail(),
+        config.rasterize_svg(),
+        config.title_prefix(),
+        false,
+        false,
+    );
+    let template = if duplicates.exhausted {
+        template.with_notice(
+            "Stopped hashing at the time limit, so some duplicates may be missing. \
+             Reload to continue."
+                .to_owned(),
+        )
+    } else {
+        template
+    };
+    Ok(HtmlTemplate(template).into_response())
 }
 
 async fn handle_request(
@@ -35,7 +227,53 @@ async fn handle_request(
     headers: HeaderMap,
     Extension(config): Extension<Arc<Config>>,
 ) -> Result<Response, AppError> {
-    let file_response = serve_file(&uri, &headers).await;
+    // Conversions only apply to the kinds of files they are made from, and other requests
+    // fall through to the file or the listing.
+    let file = local_path(&uri)
+        .ok()
+        .filter(|p| p.is_file())
+        .and_then(|p| File::new(&p, None).ok());
+    let is_kind = |kind: fn(&File) -> bool| file.as_ref().is_some_and(kind);
+
+    if let Some(size) = query.get("thumbnail").filter(|_| config.thumbnail()) {
+        return serve_thumbnail(&uri, size, &headers, &config).await;
+    }
+    if let Some(format) = query
+        .get("format")
+        .filter(|_| is_kind(|f| f.is_image() || f.is_raw()))
+    {
+        return serve_converted(&uri, format, &headers, &config).await;
+    }
+    if let Some(size) = query.get("cover") {
+        return serve_cover(&uri, size, &query, &headers, &config).await;
+    }
+    if query.contains_key("preview") && is_kind(File::is_raw) {
+        return serve_derived(&uri, &headers, &config, "preview".to_owned(), |path| {
+            raw::generate_preview(path)
+        })
+        .await;
+    }
+    if query.contains_key("render") && is_kind(File::is_markdown) {
+        return serve_markdown(&uri, &config).await;
+    }
+    if query.contains_key("vtt") && is_kind(File::is_subtitle) {
+        return serve_vtt(&uri).await;
+    }
+    if query.contains_key("view") {
+        return serve_viewer(&uri, &query, &raw_query.as_deref(), &config);
+    }
+    if query.contains_key("slideshow") {
+        return serve_slideshow(&uri, &query, &raw_query.as_deref(), &config);
+    }
+
+    if config.sanitize_svg() && svg::is_svg(&local_path(&uri)?) {
+        return serve_derived(&uri, &headers, &config, "sanitized".to_owned(), |path| {
+            svg::sanitize(path)
+        })
+        .await;
+    }
+
+    let file_response = serve_file(&uri, &headers, &config).await;
     if file_response.is_ok() || !matches!(file_response, Err(AppError::NotFound(_))) {
         return file_response;
     }
@@ -44,19 +282,25 @@ async fn handle_request(
         query.clear();
         raw_query.take();
     }
+    let accepts_json = headers
+        .get(ACCEPT)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.contains("application/json"));
+    if let Some(pattern) = query.get("search").filter(|p| !p.is_empty()) {
+        return serve_search(&uri, pattern, &query, &raw_query.as_deref(), &config);
+    }
+    if accepts_json {
+        return serve_json(&uri, &query, &config);
+    }
     serve_dir(&uri, &query, &raw_query.as_deref(), &config)
 }
 
-async fn serve_file(uri: &Uri, headers: &HeaderMap) -> Result<Response, AppError> {
-    let mut req = Request::builder().uri(uri);
-    let headers_mut = req.headers_mut().unwrap();
-    for (k, v) in headers.iter() {
-        headers_mut.insert(k, v.to_owned());
-    }
-    let req = req
-        .body(Body::empty())
-        .map_err(|e| AppError::BadRequest(e.into()))?;
-
+async fn serve_file(
+    uri: &Uri,
+    headers: &HeaderMap,
+    config: &Arc<Config>,
+) -> Result<Response, AppError> {
+    let req = build_request(uri, headers)?;
     match ServeDir::new(".")
         .append_index_html_on_directories(false)
         .oneshot(req)
@@ -67,6 +311,15 @@ async fn serve_file(uri: &Uri, headers: &HeaderMap) -> Result<Response, AppError
             _ => {
                 res.headers_mut()
                     .insert(CACHE_CONTROL, "no-cache".parse().unwrap());
+                res.headers_mut()
+                    .insert(X_CONTENT_TYPE_OPTIONS, "nosniff".parse().unwrap());
+                restrict_svg(&mut res);
+                if config.sniff_content() && res.status().is_success() {
+                    if let Some(mime) = sniff::sniff(&local_path(uri)?) {
+                        res.headers_mut()
+                            .insert(CONTENT_TYPE, mime.parse().unwrap());
+                    }
+                }
                 Ok(res.into_response())
             }
         },
@@ -74,26 +327,302 @@ async fn serve_file(uri: &Uri, headers: &HeaderMap) -> Result<Response, AppError
     }
 }
 
+async fn serve_thumbnail(
+    uri: &Uri,
+    size: &str,
+    headers: &HeaderMap,
+    config: &Arc<Config>,
+) -> Result<Response, AppError> {
+    let size: u32 = size
+        .parse()
+        .map_err(|e: std::num::ParseIntError| AppError::BadRequest(e.into()))?;
+    let size = thumbnail::bucket(size);
+    let response = serve_derived(
+        uri,
+        headers,
+        config,
+        format!("thumbnail-{}", size),
+        move |path| thumbnail::generate(path, size),
+    )
+    .await;
+    fallback_to_original(response, uri, headers, config).await
+}
+
+async fn serve_converted(
+    uri: &Uri,
+    format: &str,
+    headers: &HeaderMap,
+    config: &Arc<Config>,
+) -> Result<Response, AppError> {
+    let format: OutputFormat = format
+        .parse()
+        .map_err(|e: String| AppError::BadRequest(anyhow!(e)))?;
+    let response = serve_derived(
+        uri,
+        headers,
+        config,
+        format!("format-{}", format.name()),
+        move |path| convert::convert(path, format),
+    )
+    .await;
+    fallback_to_original(response, uri, headers, config).await
+}
+
+async fn serve_cover(
+    uri: &Uri,
+    size: &str,
+    query: &HashMap<String, String>,
+    headers: &HeaderMap,
+    config: &Arc<Config>,
+) -> Result<Response, AppError> {
+    let size = match size {
+        "" => None,
+        size => Some(thumbnail::bucket(size.parse().map_err(
+            |e: std::num::ParseIntError| AppError::BadRequest(e.into()),
+        )?)),
+    };
+    if local_path(uri)?.is_dir() {
+        return serve_dir_cover(uri, size, query, headers, config).await;
+    }
+
+    let variant = match size {
+        Some(size) => format!("cover-{}", size),
+        None => "cover".to_owned(),
+    };
+    serve_derived(uri, headers, config, variant, move |path| {
+        tags::extract_cover(path, size)
+    })
+    .await
+}
+
+// Serves a thumbnail of the image that represents the directory, picked by the sort order
+// of the listing that shows it.
+async fn serve_dir_cover(
+    uri: &Uri,
+    size: Option<u32>,
+    query: &HashMap<String, String>,
+    headers: &HeaderMap,
+    config: &Arc<Config>,
+) -> Result<Response, AppError> {
+    let path = local_path(uri)?;
+    let decoded_uri = percent_encoding::percent_decode_str(uri.path()).decode_utf8_lossy();
+    let config = config.for_dir(&decoded_uri);
+    let query = if config.ignore_query_params() {
+        HashMap::new()
+    } else {
+        query.clone()
+    };
+    let order = extract_order(&query, &config);
+    let reverse = query_param(&query, "reverse", || config.reverse());
+
+    let cache_dir = config.cache_dir().to_owned();
+    let cache_size = config.cache_size();
+    let cover = tokio::task::spawn_blocking(move || {
+        let cover = dir_cover::find(&path, &order, reverse)
+            .ok_or_else(|| anyhow!("No cover image in {}", path.display()))?;
+        let Some(size) = size else {
+            return Ok(cover);
+        };
+        cache::get_or_create(
+            &cache_dir,
+            cache_size,
+            &cover,
+            &format!("thumbnail-{}", size),
+            || thumbnail::generate(&cover, size),
+        )
+        .or_else(|e| {
+            tracing::debug!(
+                "Failed to generate thumbnail, fallback to original: {:?}",
+                e
+            );
+            Ok(cover)
+        })
+    })
+    .await
+    .map_err(|e| anyhow!(e))?;
+
+    match cover {
+        Ok(cover) => serve_cached(uri, &cover, headers).await,
+        Err(e) => Err(AppError::NotFound(e)),
+    }
+}
+
+async fn serve_derived<F>(
+    uri: &Uri,
+    headers: &HeaderMap,
+    config: &Arc<Config>,
+    variant: String,
+    create: F,
+) -> Result<Response, AppError>
+where
+    F: FnOnce(&Path) -> Result<(Vec<u8>, &'static str)> + Send + 'static,
+{
+    let path = local_path(uri)?;
+    if !path.is_file() {
+        return Err(AppError::NotFound(anyhow!(
+            "{} is not a file",
+            path.display()
+        )));
+    }
+
+    let cache_dir = config.cache_dir().to_owned();
+    let cache_size = config.cache_size();
+    let cached = tokio::task::spawn_blocking(move || {
+        cache::get_or_create(&cache_dir, cache_size, &path, &variant, || create(&path))
+    })
+    .await
+    .map_err(|e| anyhow!(e))?;
+
+    match cached {
+        Ok(cached_path) => serve_cached(uri, &cached_path, headers).await,
+        Err(e) => Err(AppError::NotFound(e)),
+    }
+}
+
+async fn fallback_to_original(
+    response: Result<Response, AppError>,
+    uri: &Uri,
+    headers: &HeaderMap,
+    config: &Arc<Config>,
+) -> Result<Response, AppError> {
+    match response {
+        Err(AppError::NotFound(e)) => {
+            tracing::debug!(
+                "Failed to generate derived image, fallback to original: {:?}",
+                e
+            );
+            serve_file(uri, headers, config).await
+        }
+        response => response,
+    }
+}
+
+async fn serve_cached(
+    uri: &Uri,
+    cached_path: &Path,
+    headers: &HeaderMap,
+) -> Result<Response, AppError> {
+    let req = build_request(uri, headers)?;
+    match ServeFile::new(cached_path).oneshot(req).await {
+        Ok(mut res) => {
+            res.headers_mut()
+                .insert(CACHE_CONTROL, "no-cache".parse().unwrap());
+            res.headers_mut()
+                .insert(X_CONTENT_TYPE_OPTIONS, "nosniff".parse().unwrap());
+            restrict_svg(&mut res);
+            Ok(res.into_response())
+        }
+        Err(e) => Err(anyhow!(e).into()),
+    }
+}
+
+async fn serve_markdown(uri: &Uri, config: &Arc<Config>) -> Result<Response, AppError> {
+    let path = local_path(uri)?;
+    if !path.is_file() {
+        return Err(AppError::NotFound(anyhow!(
+            "{} is not a file",
+            path.display()
+        )));
+    }
+
+    let content = tokio::task::spawn_blocking(move || preview::render_markdown(&path))
+        .await
+        .map_err(|e| anyhow!(e))??;
+    let decoded_uri = percent_encoding::percent_decode_str(uri.path()).decode_utf8_lossy();
+    let template = MarkdownTemplate::new(&decoded_uri, &content, config.title_prefix());
+    Ok(HtmlTemplate(template).into_response())
+}
+
+async fn serve_vtt(uri: &Uri) -> Result<Response, AppError> {
+    let path = local_path(uri)?;
+    if !path.is_file() {
+        return Err(AppError::NotFound(anyhow!(
+            "{} is not a file",
+            path.display()
+        )));
+    }
+
+    let content = tokio::task::spawn_blocking(move || subtitle::to_vtt(&path))
+        .await
+        .map_err(|e| anyhow!(e))??;
+    Ok((
+        [
+            (CONTENT_TYPE, "text/vtt; charset=utf-8"),
+            (CACHE_CONTROL, "no-cache"),
+        ],
+        content,
+    )
+        .into_response())
+}
+
+fn restrict_svg<B>(res: &mut Response<B>) {
+    let is_svg = res
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("image/svg+xml"));
+    if is_svg {
+        res.headers_mut().insert(
+            CONTENT_SECURITY_POLICY,
+            svg::CONTENT_SECURITY_POLICY.parse().unwrap(),
+        );
+    }
+}
+
+// The directory that an API request under `prefix` is about.
+fn api_target(uri: &Uri, prefix: &str) -> Result<Uri, AppError> {
+    let path = uri.path().trim_start_matches(prefix);
+    format!("/{}/", path.trim_matches('/'))
+        .replace("//", "/")
+        .parse()
+        .map_err(|e: axum::http::uri::InvalidUri| AppError::BadRequest(e.into()))
+}
+
+fn build_request(uri: &Uri, headers: &HeaderMap) -> Result<Request<Body>, AppError> {
+    let mut req = Request::builder().uri(uri);
+    let headers_mut = req.headers_mut().unwrap();
+    for (k, v) in headers.iter() {
+        headers_mut.insert(k, v.to_owned());
+    }
+    req.body(Body::empty())
+        .map_err(|e| AppError::BadRequest(e.into()))
+}
+
+fn local_path(uri: &Uri) -> Result<PathBuf, AppError> {
+    let decoded_uri = percent_encoding::percent_decode_str(uri.path()).decode_utf8_lossy();
+    let path = PathBuf::from(format!(".{}", decoded_uri));
+    if path.components().any(|c| c == Component::ParentDir) {
+        return Err(AppError::BadRequest(anyhow!(
+            "Invalid path: {}",
+            decoded_uri
+        )));
+    }
+    Ok(path)
+}
+
 fn serve_dir(
     uri: &Uri,
     query: &HashMap<String, String>,
     raw_query: &Option<&str>,
     config: &Arc<Config>,
 ) -> Result<Response, AppError> {
+    local_path(uri)?;
     let decoded_uri = percent_encoding::percent_decode_str(uri.path()).decode_utf8_lossy();
-    let containers = walk_dir(&decoded_uri, query, config)?;
+    let config = &config.for_dir(&decoded_uri);
+    let containers = walk_dir(&decoded_uri, query, config, true)?;
 
     let raw_query = raw_query.map(|r| format!("?{}", r)).unwrap_or_default();
-    let lazy = query
-        .get("lazy")
-        .and_then(|r| r.parse().ok())
-        .unwrap_or_else(|| config.lazy());
+    let lazy = query_param(query, "lazy", || config.lazy());
+    let animate = query_param(query, "animate", || config.animate());
 
     let template = RatticeTemplate::new(
         &decoded_uri,
         &raw_query,
         containers,
         lazy,
+        animate,
+        config.thumbnail(),
+        config.rasterize_svg(),
         config.title_prefix(),
         false,
         false,
@@ -101,35 +630,356 @@ fn serve_dir(
     Ok(HtmlTemplate(template).into_response())
 }
 
-pub(crate) fn walk_dir(
-    uri: &str,
+// Sends the page header right away and then the matches of each directory as they are found.
+fn serve_search(
+    uri: &Uri,
+    pattern: &str,
     query: &HashMap<String, String>,
+    raw_query: &Option<&str>,
     config: &Arc<Config>,
-) -> Result<Vec<FilesContainer>, AppError> {
+) -> Result<Response, AppError> {
+    let path = local_path(uri)?;
+    if !path.is_dir() {
+        return Err(AppError::NotFound(anyhow!(
+            "No such directory: {}",
+            path.display()
+        )));
+    }
+    let decoded_uri = percent_encoding::percent_decode_str(uri.path())
+        .decode_utf8_lossy()
+        .into_owned();
+    let config = &config.for_dir(&decoded_uri);
     let re_dir = extract_regex("filter_dir", query, || config.filter_dir_pattern())?;
     let re_file = extract_regex("filter_file", query, || config.filter_file_pattern())?;
-    let filter_op = |e: &DirEntry| filter_entry(e, &re_dir, &re_file);
+    let regex = search::build_pattern(pattern)?;
+    let search = pattern.to_owned();
+
+    let order = extract_order(query, config);
+    let reverse = query_param(query, "reverse", || config.reverse());
+    let lazy = query_param(query, "lazy", || config.lazy());
+    let animate = query_param(query, "animate", || config.animate());
+    let file_ops = FileOps::new(query, config, &order);
+
+    // Links in the results lead to plain listings rather than to another search.
+    let link_query = raw_query
+        .map(|r| {
+            r.split('&')
+                .filter(|p| !p.is_empty() && !p.starts_with("search="))
+                .collect::<Vec<_>>()
+                .join("&")
+        })
+        .filter(|r| !r.is_empty())
+        .map(|r| format!("?{}", r))
+        .unwrap_or_default();
+
+    let (tx, mut rx) = tokio::sync::mpsc::channel::<String>(SEARCH_CHANNEL_CAPACITY);
+    let config = config.clone();
+    tokio::task::spawn_blocking(move || {
+        let template = |part, containers| {
+            RatticeTemplate::new(
+                &decoded_uri,
+                &link_query,
+                containers,
+                lazy,
+                animate,
+                config.thumbnail(),
+                config.rasterize_svg(),
+                config.title_prefix(),
+                false,
+                false,
+            )
+            .with_part(part)
+            .with_search(&search)
+        };
+        let send = |template: RatticeTemplate| match template.render() {
+            Ok(html) => tx.blocking_send(html).is_ok(),
+            Err(e) => {
+                tracing::error!("Failed to render search results: {}", e);
+                false
+            }
+        };
+
+        if !send(template(PagePart::Header, vec![])) {
+            return;
+        }
+        // A slow client holds up the walk once the channel is full, which only affects the
+        // threads of searches.
+        let (matches_tx, matches_rx) = std::sync::mpsc::sync_channel(SEARCH_CHANNEL_CAPACITY);
+        let summary = std::thread::scope(|scope| {
+            let walk = scope.spawn(|| {
+                // Dropped when the walk ends, which ends the loop below.
+                let matches_tx = matches_tx;
+                search::search(
+                    &decoded_uri,
+                    &regex,
+                    |e| filter_entry(e, &re_dir, &re_file),
+                    |dir, entries| {
+                        let files = entries
+                            .par_iter()
+                            .filter_map(|e| File::new(&e.path(), e.metadata().ok()).ok())
+                            .filter_map(|f| file_ops.map(f))
+                            .map(|f| file_ops.decorate(f))
+                            .collect();
+                        let mut files = subtitle::attach_sidecars(files);
+                        files.par_sort_unstable_by(|a, b| a.cmp_by(b, &order, reverse));
+                        matches_tx.send(FilesContainer::new(dir, files)).is_ok()
+                    },
+                )
+            });
+            for container in matches_rx {
+                if !send(template(PagePart::Containers, vec![container])) {
+                    break;
+                }
+            }
+            walk.join()
+        });
+        let Ok(summary) = summary else {
+            tracing::error!("Search in {} panicked", decoded_uri);
+            return;
+        };
+
+        let notice = if summary.exhausted {
+            format!(
+                "Stopped searching after {} entries, so some matches may be missing.",
+                summary.visited
+            )
+        } else {
+            format!(
+                "Found {} files among {} entries.",
+                summary.matched, summary.visited
+            )
+        };
+        send(template(PagePart::Footer, vec![]).with_notice(notice));
+    });
+
+    let (mut sender, body) = hyper::Body::channel();
+    tokio::spawn(async move {
+        while let Some(html) = rx.recv().await {
+            if sender.send_data(html.into()).await.is_err() {
+                break;
+            }
+        }
+    });
+    Response::builder()
+        .header(CONTENT_TYPE, "text/html; charset=utf-8")
+        .body(body)
+        .map(IntoResponse::into_response)
+        .map_err(|e| AppError::InternalServerError(e.into()))
+}
+
+// Steps through the same listing that the directory page shows, so the query is passed on.
+fn serve_viewer(
+    uri: &Uri,
+    query: &HashMap<String, String>,
+    raw_query: &Option<&str>,
+    config: &Arc<Config>,
+) -> Result<Response, AppError> {
+    let path = local_path(uri)?;
+    if !path.is_file() {
+        return Err(AppError::NotFound(anyhow!(
+            "{} is not a file",
+            path.display()
+        )));
+    }
+    let decoded_uri = percent_encoding::percent_decode_str(uri.path()).decode_utf8_lossy();
+    let (dir_uri, name) = decoded_uri.rsplit_once('/').unwrap_or(("", &decoded_uri));
+    let dir_uri = format!("{}/", dir_uri);
+    let config = config.for_dir(&dir_uri);
+
+    let (mut query, raw_query) = if config.ignore_query_params() {
+        (HashMap::new(), "?view".to_owned())
+    } else {
+        let params = raw_query
+            .unwrap_or_default()
+            .split('&')
+            .filter(|p| !p.is_empty() && !p.starts_with("page=") && !p.starts_with("search="))
+            .collect::<Vec<_>>();
+        (query.clone(), format!("?{}", params.join("&")))
+    };
+    query.insert("depth".to_owned(), "1".to_owned());
+    query.insert("per_page".to_owned(), "0".to_owned());
+    let containers = walk_dir(&dir_uri, &query, &config, false)?;
+
+    let files = containers
+        .first()
+        .map(|c| {
+            c.files()
+                .iter()
+                .filter(|f| f.is_viewable())
+                .cloned()
+                .collect()
+        })
+        .unwrap_or_else(Vec::new);
+    let index = files
+        .iter()
+        .position(|f| f.name() == name)
+        .ok_or_else(|| AppError::NotFound(anyhow!("{} is not listed", decoded_uri)))?;
+
+    let template = ViewerTemplate::new(
+        &decoded_uri,
+        &raw_query,
+        &dir_uri,
+        files,
+        index,
+        config.title_prefix(),
+    );
+    Ok(HtmlTemplate(template).into_response())
+}
+
+fn serve_slideshow(
+    uri: &Uri,
+    query: &HashMap<String, String>,
+    raw_query: &Option<&str>,
+    config: &Arc<Config>,
+) -> Result<Response, AppError> {
+    let path = local_path(uri)?;
+    if !path.is_dir() {
+        return Err(AppError::NotFound(anyhow!(
+            "{} is not a directory",
+            path.display()
+        )));
+    }
+    let decoded_uri = percent_encoding::percent_decode_str(uri.path()).decode_utf8_lossy();
+    let config = config.for_dir(&decoded_uri);
+    let query = if config.ignore_query_params() {
+        HashMap::new()
+    } else {
+        query.clone()
+    };
+
+    let seconds = |key: &str, default: f64| {
+        query
+            .get(key)
+            .and_then(|v| v.parse::<f64>().ok())
+            .filter(|v| v.is_finite())
+            .unwrap_or(default)
+    };
+    let interval = seconds("interval", SLIDESHOW_INTERVAL).max(1.0);
+    let fade = seconds("fade", SLIDESHOW_FADE).clamp(0.0, interval);
+    let refresh = seconds("refresh", SLIDESHOW_REFRESH).max(10.0);
+    let shuffle = query.get("shuffle").is_some_and(|s| s != "false");
+    let seed = query_param(&query, "seed", || u64::from(rand::random::<u32>()));
+
+    // The playlist gets the filters and the depth, while the slideshow settings stay here.
+    let params = if config.ignore_query_params() {
+        vec![]
+    } else {
+        raw_query
+            .unwrap_or_default()
+            .split('&')
+            .filter(|p| {
+                let key = p.split('=').next().unwrap_or_default();
+                !p.is_empty() && !SLIDESHOW_PARAMS.contains(&key)
+            })
+            .collect::<Vec<_>>()
+    };
+    let playlist = format!("/_rattice/api/slideshow{}?{}", uri.path(), params.join("&"));
 
-    let order = query.get("order").and_then(|o| o.parse().ok());
-    let order = order.as_ref().unwrap_or_else(|| config.sort_order());
-    let reverse = query
-        .get("reverse")
-        .and_then(|r| r.parse().ok())
-        .unwrap_or_else(|| config.reverse());
+    let template = SlideshowTemplate::new(
+        &decoded_uri,
+        &playlist,
+        interval,
+        fade,
+        refresh,
+        shuffle.then_some(seed),
+        config.title_prefix(),
+    );
+    Ok(HtmlTemplate(template).into_response())
+}
 
-    let depth = query
-        .get("depth")
-        .and_then(|o| o.parse().ok())
-        .unwrap_or(config.depth());
+fn serve_json(
+    uri: &Uri,
+    query: &HashMap<String, String>,
+    config: &Arc<Config>,
+) -> Result<Response, AppError> {
+    local_path(uri)?;
+    let decoded_uri = percent_encoding::percent_decode_str(uri.path()).decode_utf8_lossy();
+    let config = &config.for_dir(&decoded_uri);
+    let containers = walk_dir(&decoded_uri, query, config, true)?;
+    let listing = Listing::new(&decoded_uri, extract_depth(query, config), &containers);
+    Ok(Json(listing).into_response())
+}
+
+// Files are read for what the page shows, such as stats, tags and previews, only with
+// `decorate`. Without it, they get only what sorting and filtering need.
+pub(crate) fn walk_dir(
+    uri: &str,
+    query: &HashMap<String, String>,
+    config: &Arc<Config>,
+    decorate: bool,
+) -> Result<Vec<FilesContainer>, AppError> {
+    let depth = extract_depth(query, config);
+    let page = query_param(query, "page", || 1).max(1);
+    let per_page = query_param(query, "per_page", || config.per_page());
 
+    // Directories that are not reached in time are shown without stats.
+    let deadline = Instant::now() + DIR_STATS_TIME_LIMIT;
     let mut containers = vec![];
     let mut next_targets = vec![uri.to_owned()];
 
     for i in 0..depth {
         let mut child_containers = vec![];
         for target_uri in &next_targets {
-            let files = list_files(target_uri, order, reverse, filter_op, i == 0)?;
-            child_containers.push(FilesContainer::new(target_uri, files))
+            // Subdirectories are listed with their own `.rattice.toml`.
+            let config = &config.for_dir(target_uri);
+            let filter_dir = extract_pattern("filter_dir", query, || config.filter_dir_pattern());
+            let filter_file =
+                extract_pattern("filter_file", query, || config.filter_file_pattern());
+            let re_dir = build_regex(filter_dir)?;
+            let re_file = build_regex(filter_file)?;
+            let order = &extract_order(query, config);
+            let reverse = query_param(query, "reverse", || config.reverse());
+            let file_ops = FileOps::new(query, config, order);
+            let file_ops = if decorate {
+                file_ops.with_dir_stats(config.directory_sizes(), deadline)
+            } else {
+                file_ops
+            };
+
+            let files = list_files(
+                target_uri,
+                order,
+                reverse,
+                |e| filter_entry(e, &re_dir, &re_file),
+                |f| file_ops.map(f),
+            )?;
+
+            // Only the requested directory is paged through. Subdirectories show their first
+            // page, which links to the rest of their own listing, and only those on the shown
+            // page are descended into.
+            let page = if i == 0 { page } else { 1 };
+            let (files, page_count) = paginate(files, page, per_page);
+            // Only the files on the current page are decorated.
+            let mut files = if decorate {
+                files
+                    .into_par_iter()
+                    .map(|f| file_ops.decorate(f))
+                    .collect::<Vec<_>>()
+            } else {
+                files
+            };
+            if target_uri != "/" && i == 0 {
+                files.insert(
+                    0,
+                    File::new_with_name(
+                        Path::new(&format!(".{}", target_uri)).parent().unwrap(),
+                        "..",
+                        None,
+                    )?,
+                )
+            }
+            let params = ListingParams {
+                order: order.clone(),
+                reverse,
+                filter_dir: filter_dir.map(str::to_owned),
+                filter_file: filter_file.map(str::to_owned),
+                per_page,
+            };
+            child_containers.push(
+                FilesContainer::new(target_uri, files)
+                    .with_pages(page, page_count)
+                    .with_params(params),
+            )
         }
 
         if i < depth - 1 {
@@ -154,44 +1004,177 @@ pub(crate) fn walk_dir(
     Ok(containers)
 }
 
+// The metadata that listings and search results read for each file.
+struct FileOps {
+    sniff_content: bool,
+    read_exif: bool,
+    probe_dimensions: bool,
+    min_width: Option<u32>,
+    min_height: Option<u32>,
+    sort_by_size: bool,
+    // Whether directories get stats of their whole subtree, and when to give up on them.
+    dir_stats: Option<(bool, Instant)>,
+}
+
+impl FileOps {
+    fn new(query: &HashMap<String, String>, config: &Config, order: &SortOrder) -> Self {
+        let min_width = query.get("min_width").and_then(|w| w.parse().ok());
+        let min_height = query.get("min_height").and_then(|h| h.parse().ok());
+        let probe_dimensions = config.probe_dimensions()
+            || matches!(order, SortOrder::Pixels | SortOrder::AspectRatio)
+            || min_width.is_some()
+            || min_height.is_some();
+        Self {
+            sniff_content: config.sniff_content(),
+            // The orientation is needed to report dimensions as displayed.
+            read_exif: config.exif() || matches!(order, SortOrder::TakenAt) || probe_dimensions,
+            probe_dimensions,
+            min_width,
+            min_height,
+            sort_by_size: matches!(order, SortOrder::Size),
+            dir_stats: None,
+        }
+    }
+
+    fn with_dir_stats(mut self, recursive: bool, deadline: Instant) -> Self {
+        self.dir_stats = Some((recursive, deadline));
+        self
+    }
+
+    // Sorting directories by size needs the stats of all of them, not only the shown ones.
+    fn needs_stats_to_sort(&self) -> bool {
+        self.sort_by_size && self.dir_stats.is_some_and(|(recursive, _)| recursive)
+    }
+
+    fn add_stats(&self, f: File) -> File {
+        match self.dir_stats {
+            Some((recursive, deadline)) if f.is_dir() => f.with_stats(recursive, deadline),
+            _ => f,
+        }
+    }
+
+    // Reads what sorting and filtering need, and drops images below the minimum size.
+    fn map(&self, f: File) -> Option<File> {
+        let f = if self.sniff_content {
+            f.with_sniffed_mime()
+        } else {
+            f
+        };
+        let f = if self.read_exif { f.with_exif() } else { f };
+        let f = if self.probe_dimensions {
+            f.with_dimensions()
+        } else {
+            f
+        };
+        // Files without readable dimensions, such as videos and text, are kept.
+        if let Some((width, height)) = f.dimensions().filter(|_| f.is_image()) {
+            if width < self.min_width.unwrap_or(0) || height < self.min_height.unwrap_or(0) {
+                return None;
+            }
+        }
+        if self.needs_stats_to_sort() {
+            Some(self.add_stats(f))
+        } else {
+            Some(f)
+        }
+    }
+
+    // Reads what only the shown files need.
+    fn decorate(&self, f: File) -> File {
+        let f = if self.needs_stats_to_sort() {
+            f
+        } else {
+            self.add_stats(f)
+        };
+        f.with_animation().with_tags().with_preview()
+    }
+}
+
 fn list_files(
     uri: &str,
     order: &SortOrder,
     reverse: bool,
     filter_op: impl Fn(&DirEntry) -> bool + Sync + Send,
-    add_parent: bool,
+    map_op: impl Fn(File) -> Option<File> + Sync + Send,
 ) -> Result<Vec<File>, AppError> {
     let entries = std::fs::read_dir(format!(".{}", uri))
         .map_err(|e| AppError::NotFound(e.into()))?
         .collect::<Result<Vec<_>, _>>()
         .map_err(|e| AppError::NotFound(e.into()))?;
 
-    let mut files = entries
+    let files = entries
         .par_iter()
         .filter(|e| filter_op(e))
         .map(|e| File::new(&e.path(), e.metadata().ok()))
+        .filter_map(|f| f.map(&map_op).transpose())
         .collect::<Result<Vec<_>>>()?;
 
+    let mut files = subtitle::attach_sidecars(files);
     files.par_sort_unstable_by(|a, b| a.cmp_by(b, order, reverse));
-    if uri != "/" && add_parent {
-        files.insert(
-            0,
-            File::new_with_name(
-                Path::new(&format!(".{}", uri)).parent().unwrap(),
-                "..",
-                None,
-            )?,
-        )
-    }
     Ok(files)
 }
 
+fn paginate(files: Vec<File>, page: usize, per_page: usize) -> (Vec<File>, usize) {
+    if per_page == 0 {
+        return (files, 1);
+    }
+
+    let page_count = files.len().div_ceil(per_page).max(1);
+    let files = files
+        .into_iter()
+        .skip((page - 1).saturating_mul(per_page))
+        .take(per_page)
+        .collect();
+    (files, page_count)
+}
+
+// The value of `key` in the query, or `default` when it is missing or invalid.
+fn query_param<T: FromStr>(
+    query: &HashMap<String, String>,
+    key: &str,
+    default: impl FnOnce() -> T,
+) -> T {
+    query
+        .get(key)
+        .and_then(|v| v.parse().ok())
+        .unwrap_or_else(default)
+}
+
+fn extract_depth(query: &HashMap<String, String>, config: &Config) -> u32 {
+    query_param(query, "depth", || config.depth())
+}
+
+// `seed` only applies to the random order, and falls back to the seed of the configured one.
+fn extract_order(query: &HashMap<String, String>, config: &Arc<Config>) -> SortOrder {
+    let order = query
+        .get("order")
+        .and_then(|o| o.parse::<SortOrder>().ok())
+        .unwrap_or_else(|| config.sort_order().clone());
+    let seed = query
+        .get("seed")
+        .and_then(|s| s.parse().ok())
+        .or_else(|| config.sort_order().seed())
+        .unwrap_or(0);
+    order.with_seed(seed)
+}
+
 fn extract_regex<'a>(
     key: &'a str,
     query: &'a HashMap<String, String>,
     default_pattern: impl FnOnce() -> Option<&'a str>,
 ) -> Result<Option<Regex>, AppError> {
-    let pattern = query.get(key).map(|p| p.as_str()).or_else(default_pattern);
+    build_regex(extract_pattern(key, query, default_pattern))
+}
+
+fn extract_pattern<'a>(
+    key: &'a str,
+    query: &'a HashMap<String, String>,
+    default_pattern: impl FnOnce() -> Option<&'a str>,
+) -> Option<&'a str> {
+    query.get(key).map(|p| p.as_str()).or_else(default_pattern)
+}
+
+fn build_regex(pattern: Option<&str>) -> Result<Option<Regex>, AppError> {
     let regex = match pattern {
         Some(pattern) => Some(
             RegexBuilder::new(pattern)
@@ -205,7 +1188,9 @@ fn extract_regex<'a>(
 }
 
 fn filter_entry(entry: &DirEntry, regex_dir: &Option<Regex>, regex_file: &Option<Regex>) -> bool {
-    if entry.path().is_dir() {
+    if entry.file_name() == DIR_CONFIG_FILE_NAME {
+        false
+    } else if entry.path().is_dir() {
         is_filename_match(entry, regex_dir)
     } else {
         is_filename_match(entry, regex_file)
@@ -219,3 +1204,44 @@ fn is_filename_match(entry: &DirEntry, regex: &Option<Regex>) -> bool {
         true
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn files(count: usize) -> Vec<File> {
+        (1..=count)
+            .map(|i| File::new(Path::new(&format!("./{}.png", i)), None).unwrap())
+            .collect()
+    }
+
+    fn names(files: &[File]) -> Vec<&str> {
+        files.iter().map(|f| f.name()).collect()
+    }
+
+    #[test]
+    fn paginates() {
+        let (page, count) = paginate(files(5), 1, 2);
+        assert_eq!((names(&page), count), (vec!["1.png", "2.png"], 3));
+        let (page, count) = paginate(files(5), 3, 2);
+        assert_eq!((names(&page), count), (vec!["5.png"], 3));
+        let (page, count) = paginate(files(4), 2, 2);
+        assert_eq!((names(&page), count), (vec!["3.png", "4.png"], 2));
+    }
+
+    #[test]
+    fn shows_everything_without_per_page() {
+        let (page, count) = paginate(files(5), 2, 0);
+        assert_eq!((page.len(), count), (5, 1));
+    }
+
+    #[test]
+    fn shows_nothing_past_the_last_page() {
+        let (page, count) = paginate(files(5), 4, 2);
+        assert_eq!((page.len(), count), (0, 3));
+        let (page, count) = paginate(files(5), usize::MAX, 2);
+        assert_eq!((page.len(), count), (0, 3));
+        let (page, count) = paginate(files(0), 1, 2);
+        assert_eq!((page.len(), count), (0, 1));
+    }
+}
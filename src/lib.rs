@@ -1,8 +1,26 @@
+mod animation;
+mod api;
 pub mod auth;
+mod cache;
+mod collate;
 pub mod config;
+mod convert;
+mod dir_cover;
+mod dir_mtimes;
+mod dir_stats;
+mod duplicates;
 mod error;
+mod exif;
 pub mod handle;
 pub mod model;
 pub mod generate;
+mod preview;
+mod raw;
+mod search;
+mod sniff;
+mod subtitle;
+mod svg;
+mod tags;
 mod template;
+mod thumbnail;
 pub mod trace;
@@ -31,6 +31,18 @@ async fn main() -> Result<()> {
         opt.ignore_query_params,
         opt.filter_dir.clone(),
         opt.filter_file.clone(),
+        !opt.no_thumbnail,
+        opt.cache_dir.clone().unwrap_or_default(),
+        opt.cache_size * 1024 * 1024,
+        opt.exif,
+        opt.sniff_content,
+        opt.probe_dimensions,
+        opt.duplicate_threshold,
+        opt.animate,
+        opt.sanitize_svg,
+        opt.rasterize_svg,
+        opt.per_page,
+        opt.directory_sizes,
     ));
 
     if opt.generate_static_pages {
@@ -1,25 +1,95 @@
-use std::{cmp::Ordering, fs::Metadata, path::Path, str::FromStr, time::SystemTime};
+use std::{
+    cmp::Ordering,
+    fs::Metadata,
+    path::Path,
+    str::FromStr,
+    time::{Instant, SystemTime},
+};
 
 use anyhow::{anyhow, Result};
 use percent_encoding::{percent_decode_str, utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
 
-const FRAGMENT: &AsciiSet = &NON_ALPHANUMERIC.remove(b'/').remove(b'.');
+use crate::{
+    animation, collate,
+    dir_stats::{self, DirStats},
+    exif::Exif,
+    preview, sniff,
+    subtitle::Subtitle,
+    tags::MediaTags,
+};
+
+pub(crate) const FRAGMENT: &AsciiSet = &NON_ALPHANUMERIC.remove(b'/').remove(b'.');
 
 const IMAGE_EXTENSIONS: &[&str] = &[
     "apng", "avif", "gif", "jpg", "jpeg", "jfif", "pjpeg", "pjp", "png", "svg", "webp", "bmp",
     "ico", "cur", "tif", "tiff",
 ];
 
+const DECODABLE_IMAGE_EXTENSIONS: &[&str] = &[
+    "gif", "jpg", "jpeg", "jfif", "pjpeg", "pjp", "png", "webp", "bmp", "ico", "tif", "tiff",
+];
+
+const CONVERTED_IMAGE_EXTENSIONS: &[&str] = &["bmp", "ico", "tif", "tiff"];
+
+const DECODABLE_IMAGE_MIME_TYPES: &[&str] = &[
+    "image/gif",
+    "image/jpeg",
+    "image/png",
+    "image/webp",
+    "image/bmp",
+    "image/vnd.microsoft.icon",
+    "image/tiff",
+];
+
+const CONVERTED_IMAGE_MIME_TYPES: &[&str] =
+    &["image/bmp", "image/vnd.microsoft.icon", "image/tiff"];
+
+const SVG_IMAGE_EXTENSIONS: &[&str] = &["svg"];
+
+const SVG_IMAGE_MIME_TYPES: &[&str] = &["image/svg+xml"];
+
+const RAW_IMAGE_EXTENSIONS: &[&str] = &["dng", "cr2", "nef", "nrw", "arw", "pef", "srw"];
+
+const RAW_IMAGE_MIME_TYPES: &[&str] = &[
+    "image/x-adobe-dng",
+    "image/x-canon-cr2",
+    "image/x-nikon-nef",
+    "image/x-sony-arw",
+    "image/x-pentax-pef",
+    "image/x-samsung-srw",
+];
+
+// `ogg` can hold Theora video as well as audio, and the video player handles both.
 const VIDEO_EXTENSIONS: &[&str] = &[
-    "3gp", "mpg", "mpeg", "mp4", "m4v", "m4p", "ogv", "ogg", "mov", "webm", "aac", "flac", "mp3",
-    "m4a", "oga", "wav",
+    "3gp", "mpg", "mpeg", "mp4", "m4v", "m4p", "ogv", "ogg", "mov", "webm",
+];
+
+const TEXT_EXTENSIONS: &[&str] = &[
+    "txt", "log", "json", "jsonl", "csv", "tsv", "yaml", "yml", "toml", "xml", "ini", "cfg",
 ];
 
+const MARKDOWN_EXTENSIONS: &[&str] = &["md", "markdown"];
+
+const PDF_EXTENSIONS: &[&str] = &["pdf"];
+
+const SUBTITLE_EXTENSIONS: &[&str] = &["srt", "vtt", "ass", "ssa"];
+
+const AUDIO_EXTENSIONS: &[&str] = &["aac", "flac", "mp3", "m4a", "oga", "opus", "wav"];
+
 #[derive(Clone)]
 pub enum SortOrder {
     Name,
+    NaturalName,
+    CaseInsensitiveName,
+    CollatedName,
     CreatedAt,
     ModifiedAt,
+    TakenAt,
+    Pixels,
+    AspectRatio,
+    Size,
+    Extension,
+    Random(u64),
 }
 
 impl FromStr for SortOrder {
@@ -28,23 +98,84 @@ impl FromStr for SortOrder {
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         match s {
             "name" => Ok(Self::Name),
+            "natural" => Ok(Self::NaturalName),
+            "ignore-case" => Ok(Self::CaseInsensitiveName),
+            "collation" => Ok(Self::CollatedName),
             "created" => Ok(Self::CreatedAt),
             "modified" => Ok(Self::ModifiedAt),
+            "taken" => Ok(Self::TakenAt),
+            "pixels" => Ok(Self::Pixels),
+            "aspect" => Ok(Self::AspectRatio),
+            "size" => Ok(Self::Size),
+            "extension" => Ok(Self::Extension),
+            "random" => Ok(Self::Random(0)),
             _ => Err(format!("Invalid variant name: {}", s)),
         }
     }
 }
 
+impl SortOrder {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Name => "name",
+            Self::NaturalName => "natural",
+            Self::CaseInsensitiveName => "ignore-case",
+            Self::CollatedName => "collation",
+            Self::CreatedAt => "created",
+            Self::ModifiedAt => "modified",
+            Self::TakenAt => "taken",
+            Self::Pixels => "pixels",
+            Self::AspectRatio => "aspect",
+            Self::Size => "size",
+            Self::Extension => "extension",
+            Self::Random(_) => "random",
+        }
+    }
+
+    pub fn seed(&self) -> Option<u64> {
+        match self {
+            Self::Random(seed) => Some(*seed),
+            _ => None,
+        }
+    }
+
+    pub fn with_seed(self, seed: u64) -> Self {
+        match self {
+            Self::Random(_) => Self::Random(seed),
+            order => order,
+        }
+    }
+}
+
 #[derive(Clone)]
 pub(crate) struct FilesContainer {
     uri: String,
     files: Vec<File>,
+    page: usize,
+    page_count: usize,
+    params: Option<ListingParams>,
+}
+
+// The parameters that a directory was listed with, after the defaults from its config.
+#[derive(Clone)]
+pub(crate) struct ListingParams {
+    pub order: SortOrder,
+    pub reverse: bool,
+    pub filter_dir: Option<String>,
+    pub filter_file: Option<String>,
+    pub per_page: usize,
 }
 
 #[derive(Clone)]
 pub(crate) enum MediaType {
     Image,
+    Raw,
     Video,
+    Audio,
+    Text,
+    Markdown,
+    Pdf,
+    Subtitle,
     Other,
 }
 
@@ -54,12 +185,20 @@ pub(crate) enum File {
         name: String,
         path: String,
         metadata: Option<Metadata>,
+        stats: Option<Box<DirStats>>,
     },
     File {
         name: String,
         path: String,
         media_type: MediaType,
         metadata: Option<Metadata>,
+        exif: Option<Box<Exif>>,
+        mime: Option<&'static str>,
+        tags: Option<Box<MediaTags>>,
+        preview: Option<String>,
+        dimensions: Option<(u32, u32)>,
+        animated: bool,
+        subtitles: Vec<Subtitle>,
     },
 }
 
@@ -69,7 +208,24 @@ impl FilesContainer {
         T: Into<String>,
     {
         let uri = uri.into();
-        FilesContainer { uri, files }
+        FilesContainer {
+            uri,
+            files,
+            page: 1,
+            page_count: 1,
+            params: None,
+        }
+    }
+
+    pub fn with_pages(mut self, page: usize, page_count: usize) -> Self {
+        self.page = page;
+        self.page_count = page_count;
+        self
+    }
+
+    pub fn with_params(mut self, params: ListingParams) -> Self {
+        self.params = Some(params);
+        self
     }
 
     pub fn uri(&self) -> &str {
@@ -79,6 +235,31 @@ impl FilesContainer {
     pub fn files(&self) -> &Vec<File> {
         &self.files
     }
+
+    pub fn page(&self) -> usize {
+        self.page
+    }
+
+    pub fn page_count(&self) -> usize {
+        self.page_count
+    }
+
+    pub fn params(&self) -> Option<&ListingParams> {
+        self.params.as_ref()
+    }
+
+    // The first, last and nearby pages, with `None` standing for the gaps in between.
+    pub fn page_numbers(&self) -> Vec<Option<usize>> {
+        let mut numbers = vec![];
+        for n in 1..=self.page_count {
+            if n == 1 || n == self.page_count || n.abs_diff(self.page) <= 2 {
+                numbers.push(Some(n));
+            } else if numbers.last() != Some(&None) {
+                numbers.push(None);
+            }
+        }
+        numbers
+    }
 }
 
 impl MediaType {
@@ -90,8 +271,20 @@ impl MediaType {
             Some(ext) => {
                 if IMAGE_EXTENSIONS.contains(&ext.as_str()) {
                     Self::Image
+                } else if RAW_IMAGE_EXTENSIONS.contains(&ext.as_str()) {
+                    Self::Raw
                 } else if VIDEO_EXTENSIONS.contains(&ext.as_str()) {
                     Self::Video
+                } else if AUDIO_EXTENSIONS.contains(&ext.as_str()) {
+                    Self::Audio
+                } else if TEXT_EXTENSIONS.contains(&ext.as_str()) {
+                    Self::Text
+                } else if MARKDOWN_EXTENSIONS.contains(&ext.as_str()) {
+                    Self::Markdown
+                } else if PDF_EXTENSIONS.contains(&ext.as_str()) {
+                    Self::Pdf
+                } else if SUBTITLE_EXTENSIONS.contains(&ext.as_str()) {
+                    Self::Subtitle
                 } else {
                     Self::Other
                 }
@@ -99,6 +292,34 @@ impl MediaType {
             None => Self::Other,
         }
     }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Image => "image",
+            Self::Raw => "raw",
+            Self::Video => "video",
+            Self::Audio => "audio",
+            Self::Text => "text",
+            Self::Markdown => "markdown",
+            Self::Pdf => "pdf",
+            Self::Subtitle => "subtitle",
+            Self::Other => "other",
+        }
+    }
+
+    pub fn from_mime(mime: &str) -> Self {
+        match mime.split_once('/') {
+            _ if RAW_IMAGE_MIME_TYPES.contains(&mime) => Self::Raw,
+            Some(("image", _)) => Self::Image,
+            Some(("video", _)) => Self::Video,
+            Some(("audio", _)) => Self::Audio,
+            Some(("text", "markdown")) => Self::Markdown,
+            Some(("text", "vtt")) => Self::Subtitle,
+            Some(("text", _)) => Self::Text,
+            Some(("application", "pdf")) => Self::Pdf,
+            _ => Self::Other,
+        }
+    }
 }
 
 impl File {
@@ -126,6 +347,7 @@ impl File {
                 name,
                 path,
                 metadata,
+                stats: None,
             }
         } else {
             Self::File {
@@ -133,12 +355,204 @@ impl File {
                 path,
                 media_type: MediaType::new(path_ref),
                 metadata,
+                exif: None,
+                mime: None,
+                tags: None,
+                preview: None,
+                dimensions: None,
+                animated: false,
+                subtitles: vec![],
             }
         };
 
         Ok(file)
     }
 
+    pub fn with_exif(mut self) -> Self {
+        let local_path = format!(".{}", self.to_uri());
+        if let Self::File {
+            name: _,
+            path: _,
+            media_type: _,
+            metadata: _,
+            exif,
+            mime: _,
+            tags: _,
+            preview: _,
+            dimensions: _,
+            animated: _,
+            subtitles: _,
+        } = &mut self
+        {
+            *exif = Exif::read(Path::new(&local_path)).map(Box::new);
+        }
+        self
+    }
+
+    pub fn with_sniffed_mime(mut self) -> Self {
+        let local_path = format!(".{}", self.to_uri());
+        if let Self::File {
+            name: _,
+            path: _,
+            media_type,
+            metadata: _,
+            exif: _,
+            mime,
+            tags: _,
+            preview: _,
+            dimensions: _,
+            animated: _,
+            subtitles: _,
+        } = &mut self
+        {
+            *mime = sniff::sniff(Path::new(&local_path));
+            // Most RAW formats are sniffed as plain TIFF.
+            if let Some(m) =
+                mime.filter(|&m| !(matches!(media_type, MediaType::Raw) && m == "image/tiff"))
+            {
+                *media_type = MediaType::from_mime(m);
+            }
+        }
+        self
+    }
+
+    pub fn with_tags(mut self) -> Self {
+        if !self.is_audio() && !self.is_video() {
+            return self;
+        }
+
+        let local_path = format!(".{}", self.to_uri());
+        if let Self::File {
+            name: _,
+            path: _,
+            media_type: _,
+            metadata: _,
+            exif: _,
+            mime: _,
+            tags,
+            preview: _,
+            dimensions: _,
+            animated: _,
+            subtitles: _,
+        } = &mut self
+        {
+            *tags = MediaTags::read(Path::new(&local_path)).map(Box::new);
+        }
+        self
+    }
+
+    pub fn with_preview(mut self) -> Self {
+        if !self.is_text() && !self.is_markdown() {
+            return self;
+        }
+
+        let local_path = format!(".{}", self.to_uri());
+        if let Self::File {
+            name: _,
+            path: _,
+            media_type: _,
+            metadata: _,
+            exif: _,
+            mime: _,
+            tags: _,
+            preview,
+            dimensions: _,
+            animated: _,
+            subtitles: _,
+        } = &mut self
+        {
+            *preview = preview::read_snippet(Path::new(&local_path));
+        }
+        self
+    }
+
+    pub fn with_stats(mut self, recursive: bool, deadline: Instant) -> Self {
+        let local_path = format!(".{}", self.to_uri());
+        if let Self::Directory {
+            name: _,
+            path: _,
+            metadata: _,
+            stats,
+        } = &mut self
+        {
+            *stats = dir_stats::compute(Path::new(&local_path), recursive, deadline).map(Box::new);
+        }
+        self
+    }
+
+    pub fn with_dimensions(mut self) -> Self {
+        if !self.is_image() {
+            return self;
+        }
+
+        let local_path = format!(".{}", self.to_uri());
+        let transposed = self.exif().is_some_and(|e| e.is_transposed());
+        if let Self::File {
+            name: _,
+            path: _,
+            media_type: _,
+            metadata: _,
+            exif: _,
+            mime: _,
+            tags: _,
+            preview: _,
+            dimensions,
+            animated: _,
+            subtitles: _,
+        } = &mut self
+        {
+            *dimensions = imagesize::size(&local_path)
+                .ok()
+                .map(|s| (s.width as u32, s.height as u32))
+                .map(|(w, h)| if transposed { (h, w) } else { (w, h) });
+        }
+        self
+    }
+
+    pub fn with_animation(mut self) -> Self {
+        if !self.is_image() {
+            return self;
+        }
+
+        let local_path = format!(".{}", self.to_uri());
+        if let Self::File {
+            name: _,
+            path: _,
+            media_type: _,
+            metadata: _,
+            exif: _,
+            mime: _,
+            tags: _,
+            preview: _,
+            dimensions: _,
+            animated,
+            subtitles: _,
+        } = &mut self
+        {
+            *animated = animation::is_animated(Path::new(&local_path));
+        }
+        self
+    }
+
+    pub fn push_subtitle(&mut self, subtitle: Subtitle) {
+        if let Self::File {
+            name: _,
+            path: _,
+            media_type: _,
+            metadata: _,
+            exif: _,
+            mime: _,
+            tags: _,
+            preview: _,
+            dimensions: _,
+            animated: _,
+            subtitles,
+        } = self
+        {
+            subtitles.push(subtitle);
+        }
+    }
+
     fn path_string_from_path_ref(path: &Path) -> Result<String> {
         path.strip_prefix("./")?
             .to_str()
@@ -155,9 +569,25 @@ impl File {
 
         let ordering = match order {
             SortOrder::Name => self.name().cmp(other.name()),
+            SortOrder::NaturalName => collate::natural_cmp(self.name(), other.name()),
+            SortOrder::CaseInsensitiveName => {
+                collate::case_insensitive_cmp(self.name(), other.name())
+            }
+            SortOrder::CollatedName => collate::collation_cmp(self.name(), other.name()),
             SortOrder::CreatedAt => self.cmp_by_created_at(other),
             SortOrder::ModifiedAt => self.cmp_by_modified_at(other),
+            SortOrder::TakenAt => self.cmp_by_taken_at(other),
+            SortOrder::Pixels => self.cmp_by_pixels(other),
+            SortOrder::AspectRatio => self.cmp_by_aspect_ratio(other),
+            SortOrder::Size => self.size().cmp(&other.size()),
+            SortOrder::Extension => self.cmp_by_extension(other),
+            SortOrder::Random(seed) => self.shuffle_key(*seed).cmp(&other.shuffle_key(*seed)),
         };
+        // Files with the same timestamp or size would otherwise come out in a different
+        // order on every request, since the sort is unstable.
+        let ordering = ordering
+            .then_with(|| self.name().cmp(other.name()))
+            .then_with(|| self.path().cmp(other.path()));
 
         if reverse {
             ordering.reverse()
@@ -172,28 +602,44 @@ impl File {
                 name,
                 path: _,
                 metadata: _,
+                stats: _,
             } => name,
             Self::File {
                 name,
                 path: _,
                 media_type: _,
                 metadata: _,
+                exif: _,
+                mime: _,
+                tags: _,
+                preview: _,
+                dimensions: _,
+                animated: _,
+                subtitles: _,
             } => name,
         }
     }
 
-    fn path(&self) -> &str {
+    pub fn path(&self) -> &str {
         match self {
             Self::Directory {
                 name: _,
                 path,
                 metadata: _,
+                stats: _,
             } => path,
             Self::File {
                 name: _,
                 path,
                 media_type: _,
                 metadata: _,
+                exif: _,
+                mime: _,
+                tags: _,
+                preview: _,
+                dimensions: _,
+                animated: _,
+                subtitles: _,
             } => path,
         }
     }
@@ -220,6 +666,7 @@ impl File {
                 name: _,
                 path: _,
                 metadata: _,
+                stats: _,
             }
         )
     }
@@ -232,6 +679,70 @@ impl File {
                 path: _,
                 media_type: MediaType::Image,
                 metadata: _,
+                exif: _,
+                mime: _,
+                tags: _,
+                preview: _,
+                dimensions: _,
+                animated: _,
+                subtitles: _,
+            }
+        )
+    }
+
+    pub fn is_raw(&self) -> bool {
+        matches!(
+            self,
+            Self::File {
+                name: _,
+                path: _,
+                media_type: MediaType::Raw,
+                metadata: _,
+                exif: _,
+                mime: _,
+                tags: _,
+                preview: _,
+                dimensions: _,
+                animated: _,
+                subtitles: _,
+            }
+        )
+    }
+
+    pub fn is_animated(&self) -> bool {
+        matches!(
+            self,
+            Self::File {
+                name: _,
+                path: _,
+                media_type: _,
+                metadata: _,
+                exif: _,
+                mime: _,
+                tags: _,
+                preview: _,
+                dimensions: _,
+                animated: true,
+                subtitles: _,
+            }
+        )
+    }
+
+    pub fn is_subtitle(&self) -> bool {
+        matches!(
+            self,
+            Self::File {
+                name: _,
+                path: _,
+                media_type: MediaType::Subtitle,
+                metadata: _,
+                exif: _,
+                mime: _,
+                tags: _,
+                preview: _,
+                dimensions: _,
+                animated: _,
+                subtitles: _,
             }
         )
     }
@@ -244,45 +755,479 @@ impl File {
                 path: _,
                 media_type: MediaType::Video,
                 metadata: _,
+                exif: _,
+                mime: _,
+                tags: _,
+                preview: _,
+                dimensions: _,
+                animated: _,
+                subtitles: _,
+            }
+        )
+    }
+
+    pub fn is_viewable(&self) -> bool {
+        self.is_image() || self.is_raw() || self.is_video() || self.is_audio()
+    }
+
+    pub fn is_audio(&self) -> bool {
+        matches!(
+            self,
+            Self::File {
+                name: _,
+                path: _,
+                media_type: MediaType::Audio,
+                metadata: _,
+                exif: _,
+                mime: _,
+                tags: _,
+                preview: _,
+                dimensions: _,
+                animated: _,
+                subtitles: _,
+            }
+        )
+    }
+
+    pub fn is_text(&self) -> bool {
+        matches!(
+            self,
+            Self::File {
+                name: _,
+                path: _,
+                media_type: MediaType::Text,
+                metadata: _,
+                exif: _,
+                mime: _,
+                tags: _,
+                preview: _,
+                dimensions: _,
+                animated: _,
+                subtitles: _,
+            }
+        )
+    }
+
+    pub fn is_markdown(&self) -> bool {
+        matches!(
+            self,
+            Self::File {
+                name: _,
+                path: _,
+                media_type: MediaType::Markdown,
+                metadata: _,
+                exif: _,
+                mime: _,
+                tags: _,
+                preview: _,
+                dimensions: _,
+                animated: _,
+                subtitles: _,
             }
         )
     }
 
+    pub fn is_pdf(&self) -> bool {
+        matches!(
+            self,
+            Self::File {
+                name: _,
+                path: _,
+                media_type: MediaType::Pdf,
+                metadata: _,
+                exif: _,
+                mime: _,
+                tags: _,
+                preview: _,
+                dimensions: _,
+                animated: _,
+                subtitles: _,
+            }
+        )
+    }
+
+    pub fn is_svg(&self) -> bool {
+        self.is_image() && self.is_type_of(SVG_IMAGE_EXTENSIONS, SVG_IMAGE_MIME_TYPES)
+    }
+
+    pub fn is_decodable_image(&self) -> bool {
+        self.is_image() && self.is_type_of(DECODABLE_IMAGE_EXTENSIONS, DECODABLE_IMAGE_MIME_TYPES)
+    }
+
+    pub fn needs_conversion(&self) -> bool {
+        self.is_image() && self.is_type_of(CONVERTED_IMAGE_EXTENSIONS, CONVERTED_IMAGE_MIME_TYPES)
+    }
+
+    pub fn preview(&self) -> Option<&str> {
+        match self {
+            Self::Directory {
+                name: _,
+                path: _,
+                metadata: _,
+                stats: _,
+            } => None,
+            Self::File {
+                name: _,
+                path: _,
+                media_type: _,
+                metadata: _,
+                exif: _,
+                mime: _,
+                tags: _,
+                preview,
+                dimensions: _,
+                animated: _,
+                subtitles: _,
+            } => preview.as_deref(),
+        }
+    }
+
+    pub fn dimensions(&self) -> Option<(u32, u32)> {
+        match self {
+            Self::Directory {
+                name: _,
+                path: _,
+                metadata: _,
+                stats: _,
+            } => None,
+            Self::File {
+                name: _,
+                path: _,
+                media_type: _,
+                metadata: _,
+                exif,
+                mime: _,
+                tags: _,
+                preview: _,
+                dimensions,
+                animated: _,
+                subtitles: _,
+            } => dimensions.or_else(|| exif.as_ref().and_then(|e| e.dimensions())),
+        }
+    }
+
+    pub fn subtitles(&self) -> &[Subtitle] {
+        match self {
+            Self::Directory {
+                name: _,
+                path: _,
+                metadata: _,
+                stats: _,
+            } => &[],
+            Self::File {
+                name: _,
+                path: _,
+                media_type: _,
+                metadata: _,
+                exif: _,
+                mime: _,
+                tags: _,
+                preview: _,
+                dimensions: _,
+                animated: _,
+                subtitles,
+            } => subtitles,
+        }
+    }
+
+    pub fn has_cover(&self) -> bool {
+        self.tags().is_some_and(|t| t.has_cover())
+    }
+
+    pub fn mime(&self) -> Option<&'static str> {
+        match self {
+            Self::Directory {
+                name: _,
+                path: _,
+                metadata: _,
+                stats: _,
+            } => None,
+            Self::File {
+                name: _,
+                path: _,
+                media_type: _,
+                metadata: _,
+                exif: _,
+                mime,
+                tags: _,
+                preview: _,
+                dimensions: _,
+                animated: _,
+                subtitles: _,
+            } => *mime,
+        }
+    }
+
+    fn is_type_of(&self, extensions: &[&str], mime_types: &[&str]) -> bool {
+        match self.mime() {
+            Some(mime) => mime_types.contains(&mime),
+            None => self
+                .extension()
+                .is_some_and(|e| extensions.contains(&e.as_str())),
+        }
+    }
+
+    fn extension(&self) -> Option<String> {
+        Path::new(self.path())
+            .extension()
+            .map(|e| e.to_string_lossy().to_ascii_lowercase())
+    }
+
+    pub fn title(&self) -> String {
+        let summary = match (self.exif(), self.tags(), self.stats()) {
+            (Some(exif), _, _) => exif.summary(),
+            (None, Some(tags), _) => tags.summary(),
+            (None, None, Some(stats)) => vec![stats.summary()],
+            (None, None, None) => vec![],
+        };
+        std::iter::once(self.name().to_owned())
+            .chain(summary)
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    pub fn tags(&self) -> Option<&MediaTags> {
+        match self {
+            Self::Directory {
+                name: _,
+                path: _,
+                metadata: _,
+                stats: _,
+            } => None,
+            Self::File {
+                name: _,
+                path: _,
+                media_type: _,
+                metadata: _,
+                exif: _,
+                mime: _,
+                tags,
+                preview: _,
+                dimensions: _,
+                animated: _,
+                subtitles: _,
+            } => tags.as_deref(),
+        }
+    }
+
+    fn exif(&self) -> Option<&Exif> {
+        match self {
+            Self::Directory {
+                name: _,
+                path: _,
+                metadata: _,
+                stats: _,
+            } => None,
+            Self::File {
+                name: _,
+                path: _,
+                media_type: _,
+                metadata: _,
+                exif,
+                mime: _,
+                tags: _,
+                preview: _,
+                dimensions: _,
+                animated: _,
+                subtitles: _,
+            } => exif.as_deref(),
+        }
+    }
+
     fn metadata(&self) -> &Option<Metadata> {
         match self {
             Self::Directory {
                 name: _,
                 path: _,
                 metadata,
+                stats: _,
             } => metadata,
             Self::File {
                 name: _,
                 path: _,
                 media_type: _,
                 metadata,
+                exif: _,
+                mime: _,
+                tags: _,
+                preview: _,
+                dimensions: _,
+                animated: _,
+                subtitles: _,
             } => metadata,
         }
     }
 
-    fn created_at(&self) -> Option<SystemTime> {
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Self::Directory {
+                name: _,
+                path: _,
+                metadata: _,
+                stats: _,
+            } => "directory",
+            Self::File {
+                name: _,
+                path: _,
+                media_type,
+                metadata: _,
+                exif: _,
+                mime: _,
+                tags: _,
+                preview: _,
+                dimensions: _,
+                animated: _,
+                subtitles: _,
+            } => media_type.name(),
+        }
+    }
+
+    // The total size of the subtree for directories, if it has been computed.
+    pub fn size(&self) -> Option<u64> {
+        if let Some(stats) = self.stats() {
+            return stats.size();
+        }
+        self.metadata()
+            .as_ref()
+            .filter(|m| m.is_file())
+            .map(|m| m.len())
+    }
+
+    pub fn stats(&self) -> Option<&DirStats> {
+        match self {
+            Self::Directory {
+                name: _,
+                path: _,
+                metadata: _,
+                stats,
+            } => stats.as_deref(),
+            Self::File {
+                name: _,
+                path: _,
+                media_type: _,
+                metadata: _,
+                exif: _,
+                mime: _,
+                tags: _,
+                preview: _,
+                dimensions: _,
+                animated: _,
+                subtitles: _,
+            } => None,
+        }
+    }
+
+    pub fn created_at(&self) -> Option<SystemTime> {
         self.metadata().clone().and_then(|m| m.created().ok())
     }
 
-    fn modified_at(&self) -> Option<SystemTime> {
+    pub fn modified_at(&self) -> Option<SystemTime> {
         self.metadata().clone().and_then(|m| m.modified().ok())
     }
 
+    fn taken_at(&self) -> Option<SystemTime> {
+        self.exif()
+            .and_then(|e| e.taken_at())
+            .or_else(|| self.modified_at())
+    }
+
     fn cmp_by_created_at(&self, other: &Self) -> Ordering {
-        match (self.created_at(), other.created_at()) {
-            (Some(s), Some(o)) => s.cmp(&o),
-            _ => Ordering::Equal,
-        }
+        // Files without the timestamp come first, as with the other optional keys.
+        self.created_at().cmp(&other.created_at())
     }
 
     fn cmp_by_modified_at(&self, other: &Self) -> Ordering {
-        match (self.modified_at(), other.modified_at()) {
-            (Some(s), Some(o)) => s.cmp(&o),
-            _ => Ordering::Equal,
+        self.modified_at().cmp(&other.modified_at())
+    }
+
+    fn cmp_by_taken_at(&self, other: &Self) -> Ordering {
+        self.taken_at().cmp(&other.taken_at())
+    }
+
+    fn cmp_by_pixels(&self, other: &Self) -> Ordering {
+        let pixels = |f: &Self| f.dimensions().map(|(w, h)| u64::from(w) * u64::from(h));
+        pixels(self).cmp(&pixels(other))
+    }
+
+    fn cmp_by_extension(&self, other: &Self) -> Ordering {
+        let extension = |f: &Self| {
+            Path::new(f.name())
+                .extension()
+                .map(|e| e.to_string_lossy().to_lowercase())
+        };
+        extension(self).cmp(&extension(other))
+    }
+
+    fn shuffle_key(&self, seed: u64) -> u64 {
+        shuffle_key(self.name(), seed)
+    }
+
+    fn cmp_by_aspect_ratio(&self, other: &Self) -> Ordering {
+        let aspect_ratio = |f: &Self| {
+            f.dimensions()
+                .filter(|(_, h)| *h > 0)
+                .map(|(w, h)| f64::from(w) / f64::from(h))
+        };
+        match (aspect_ratio(self), aspect_ratio(other)) {
+            (Some(s), Some(o)) => s.total_cmp(&o),
+            (s, o) => s.is_some().cmp(&o.is_some()),
         }
     }
 }
+
+// FNV-1a over the seed and the key, which unlike `DefaultHasher` is guaranteed to
+// give the same order on every build, so that a seed can be shared with others.
+pub(crate) fn shuffle_key(key: &str, seed: u64) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in seed.to_le_bytes().iter().chain(key.as_bytes()) {
+        hash ^= u64::from(*byte);
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    // The finalizer of SplitMix64, since FNV alone keeps similar keys close together.
+    hash = (hash ^ (hash >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    hash = (hash ^ (hash >> 27)).wrapping_mul(0x94d049bb133111eb);
+    hash ^ (hash >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn shuffled(seed: u64) -> Vec<String> {
+        let mut names = (1..=5)
+            .map(|i| format!("frame{}.png", i))
+            .collect::<Vec<_>>();
+        names.sort_by_cached_key(|name| shuffle_key(name, seed));
+        names
+    }
+
+    #[test]
+    fn shuffle_keys_are_stable() {
+        assert_eq!(shuffle_key("frame1.png", 0), 0x10b60f4da9629b7f);
+        assert_eq!(shuffle_key("frame1.png", 42), 0x55cb386be71f328e);
+    }
+
+    #[test]
+    fn seeds_give_different_orders() {
+        assert_eq!(
+            shuffled(1),
+            [
+                "frame1.png",
+                "frame5.png",
+                "frame4.png",
+                "frame3.png",
+                "frame2.png"
+            ]
+        );
+        assert_eq!(
+            shuffled(2),
+            [
+                "frame4.png",
+                "frame2.png",
+                "frame3.png",
+                "frame5.png",
+                "frame1.png"
+            ]
+        );
+    }
+}
@@ -0,0 +1,33 @@
+use std::{fs, io::Read, path::Path};
+
+use anyhow::Result;
+use pulldown_cmark::{html, Options, Parser};
+
+const SNIPPET_BYTES: u64 = 4096;
+const SNIPPET_LINES: usize = 20;
+
+pub(crate) fn read_snippet(path: &Path) -> Option<String> {
+    let mut head = vec![];
+    fs::File::open(path)
+        .and_then(|f| f.take(SNIPPET_BYTES).read_to_end(&mut head))
+        .ok()?;
+    if head.contains(&0) {
+        return None;
+    }
+
+    let text = String::from_utf8_lossy(&head);
+    Some(
+        text.lines()
+            .take(SNIPPET_LINES)
+            .collect::<Vec<_>>()
+            .join("\n"),
+    )
+}
+
+pub(crate) fn render_markdown(path: &Path) -> Result<String> {
+    let markdown = fs::read_to_string(path)?;
+    let parser = Parser::new_ext(&markdown, Options::all());
+    let mut unsafe_html = String::new();
+    html::push_html(&mut unsafe_html, parser);
+    Ok(ammonia::clean(&unsafe_html))
+}
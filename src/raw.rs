@@ -0,0 +1,363 @@
+use std::{
+    collections::HashSet,
+    fs,
+    io::{Read, Seek, SeekFrom},
+    path::Path,
+};
+
+use anyhow::{anyhow, bail, Result};
+use image::metadata::Orientation;
+
+use crate::convert::{self, OutputFormat};
+
+const MAX_IFDS: usize = 64;
+const MAX_JPEG_HEADER_LENGTH: u64 = 64 * 1024;
+
+const TAG_COMPRESSION: u16 = 0x0103;
+const TAG_STRIP_OFFSETS: u16 = 0x0111;
+const TAG_ORIENTATION: u16 = 0x0112;
+const TAG_STRIP_BYTE_COUNTS: u16 = 0x0117;
+const TAG_SUB_IFDS: u16 = 0x014a;
+const TAG_JPEG_OFFSET: u16 = 0x0201;
+const TAG_JPEG_LENGTH: u16 = 0x0202;
+
+const COMPRESSION_OLD_JPEG: u32 = 6;
+const COMPRESSION_JPEG: u32 = 7;
+
+struct Entry {
+    tag: u16,
+    field_type: u16,
+    count: u32,
+    value: [u8; 4],
+}
+
+struct TiffReader<R> {
+    file: R,
+    length: u64,
+    little_endian: bool,
+}
+
+// Serves the embedded preview as is, unless the RAW asks for a rotation.
+pub(crate) fn generate_preview(path: &Path) -> Result<(Vec<u8>, &'static str)> {
+    let (bytes, orientation) = extract_preview(path)?;
+    if orientation == Orientation::NoTransforms {
+        return Ok((bytes, OutputFormat::Jpeg.extension()));
+    }
+
+    let image = convert::decode_bytes(bytes, Some(orientation))?;
+    Ok((
+        convert::encode(&image, OutputFormat::Jpeg)?,
+        OutputFormat::Jpeg.extension(),
+    ))
+}
+
+// Picks the largest baseline or progressive JPEG among all IFDs, which skips
+// the lossless JPEG that some formats use for the sensor data itself.
+pub(crate) fn extract_preview(path: &Path) -> Result<(Vec<u8>, Orientation)> {
+    let reader =
+        TiffReader::new(fs::File::open(path)?).map_err(|e| anyhow!("{}: {}", path.display(), e))?;
+    find_preview(reader)?.ok_or_else(|| anyhow!("No embedded preview in {}", path.display()))
+}
+
+fn find_preview<R: Read + Seek>(
+    mut reader: TiffReader<R>,
+) -> Result<Option<(Vec<u8>, Orientation)>> {
+    let mut queue = vec![reader.first_ifd()?];
+    let mut visited = HashSet::new();
+    let mut orientation = None;
+    let mut candidates = vec![];
+
+    while let Some(offset) = queue.pop() {
+        if offset == 0 || visited.len() >= MAX_IFDS || !visited.insert(offset) {
+            continue;
+        }
+        let Ok((entries, next)) = reader.read_ifd(offset) else {
+            continue;
+        };
+        queue.push(next);
+
+        let value = |tag| {
+            entries
+                .iter()
+                .find(|e| e.tag == tag && e.count == 1)
+                .map(|e| reader.value(e))
+        };
+        if orientation.is_none() {
+            orientation = value(TAG_ORIENTATION);
+        }
+        if let (Some(offset), Some(length)) = (value(TAG_JPEG_OFFSET), value(TAG_JPEG_LENGTH)) {
+            candidates.push((offset, length));
+        }
+        if let (Some(COMPRESSION_OLD_JPEG | COMPRESSION_JPEG), Some(offset), Some(length)) = (
+            value(TAG_COMPRESSION),
+            value(TAG_STRIP_OFFSETS),
+            value(TAG_STRIP_BYTE_COUNTS),
+        ) {
+            candidates.push((offset, length));
+        }
+        if let Some(entry) = entries.iter().find(|e| e.tag == TAG_SUB_IFDS) {
+            queue.append(&mut reader.values(entry).unwrap_or_default());
+        }
+    }
+
+    candidates.sort_unstable_by_key(|&(_, length)| std::cmp::Reverse(length));
+    for (offset, length) in candidates {
+        if reader.is_displayable_jpeg(offset, length)? {
+            let orientation = orientation
+                .and_then(|o| Orientation::from_exif(o as u8))
+                .unwrap_or(Orientation::NoTransforms);
+            return Ok(Some((
+                reader.read_at(offset.into(), length.into())?,
+                orientation,
+            )));
+        }
+    }
+    Ok(None)
+}
+
+impl<R: Read + Seek> TiffReader<R> {
+    fn new(mut file: R) -> Result<Self> {
+        let length = file.seek(SeekFrom::End(0))?;
+        let mut header = [0; 4];
+        file.seek(SeekFrom::Start(0))?;
+        file.read_exact(&mut header)?;
+        let little_endian = match header {
+            [b'I', b'I', 42, 0] => true,
+            [b'M', b'M', 0, 42] => false,
+            _ => bail!("Not a TIFF-based RAW file"),
+        };
+        Ok(Self {
+            file,
+            length,
+            little_endian,
+        })
+    }
+
+    fn first_ifd(&mut self) -> Result<u32> {
+        let bytes = self.read_at(4, 4)?;
+        Ok(self.u32(&bytes))
+    }
+
+    fn read_ifd(&mut self, offset: u32) -> Result<(Vec<Entry>, u32)> {
+        let bytes = self.read_at(offset.into(), 2)?;
+        let count = self.u16(&bytes);
+        let bytes = self.read_at(u64::from(offset) + 2, u64::from(count) * 12 + 4)?;
+        let entries = bytes
+            .chunks_exact(12)
+            .map(|e| Entry {
+                tag: self.u16(&e[0..2]),
+                field_type: self.u16(&e[2..4]),
+                count: self.u32(&e[4..8]),
+                value: [e[8], e[9], e[10], e[11]],
+            })
+            .collect();
+        Ok((entries, self.u32(&bytes[bytes.len() - 4..])))
+    }
+
+    fn value(&self, entry: &Entry) -> u32 {
+        match entry.field_type {
+            3 => self.u16(&entry.value[0..2]).into(),
+            _ => self.u32(&entry.value),
+        }
+    }
+
+    fn values(&mut self, entry: &Entry) -> Result<Vec<u32>> {
+        if entry.count == 1 {
+            return Ok(vec![self.value(entry)]);
+        }
+        let offset = self.u32(&entry.value);
+        let bytes = self.read_at(offset.into(), u64::from(entry.count) * 4)?;
+        Ok(bytes.chunks_exact(4).map(|v| self.u32(v)).collect())
+    }
+
+    fn is_displayable_jpeg(&mut self, offset: u32, length: u32) -> Result<bool> {
+        let (offset, length) = (u64::from(offset), u64::from(length));
+        if length < 4 || offset + length > self.length {
+            return Ok(false);
+        }
+        let header = self.read_at(offset, length.min(MAX_JPEG_HEADER_LENGTH))?;
+        if header[0..2] != [0xff, 0xd8] {
+            return Ok(false);
+        }
+
+        let mut position = 2;
+        while position + 4 <= header.len() {
+            if header[position] != 0xff {
+                return Ok(false);
+            }
+            match header[position + 1] {
+                0xc0..=0xc2 => return Ok(true),
+                0xc3 | 0xc5..=0xc7 | 0xc9..=0xcb | 0xcd..=0xcf | 0xda => return Ok(false),
+                _ => {}
+            }
+            let segment_length = u16::from_be_bytes([header[position + 2], header[position + 3]]);
+            position += 2 + usize::from(segment_length);
+        }
+        Ok(false)
+    }
+
+    fn read_at(&mut self, offset: u64, length: u64) -> Result<Vec<u8>> {
+        if offset + length > self.length {
+            bail!("Offset {} is out of range", offset);
+        }
+        let mut bytes = vec![0; length as usize];
+        self.file.seek(SeekFrom::Start(offset))?;
+        self.file.read_exact(&mut bytes)?;
+        Ok(bytes)
+    }
+
+    fn u16(&self, bytes: &[u8]) -> u16 {
+        let bytes = [bytes[0], bytes[1]];
+        if self.little_endian {
+            u16::from_le_bytes(bytes)
+        } else {
+            u16::from_be_bytes(bytes)
+        }
+    }
+
+    fn u32(&self, bytes: &[u8]) -> u32 {
+        let bytes = [bytes[0], bytes[1], bytes[2], bytes[3]];
+        if self.little_endian {
+            u32::from_le_bytes(bytes)
+        } else {
+            u32::from_be_bytes(bytes)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    const TYPE_SHORT: u16 = 3;
+    const TYPE_LONG: u16 = 4;
+
+    const BASELINE_JPEG: &[u8] = &[0xff, 0xd8, 0xff, 0xc0, 0x00, 0x04, 0x08, 0x00];
+    const LOSSLESS_JPEG: &[u8] = &[
+        0xff, 0xd8, 0xff, 0xc3, 0x00, 0x04, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00,
+    ];
+
+    // The offset, the entries as tag, type and value, and the offset of the next IFD.
+    type Ifd<'a> = (u32, &'a [(u16, u16, u32)], u32);
+
+    // A little-endian TIFF with the given IFDs and blobs at fixed offsets.
+    fn tiff(ifds: &[Ifd], blobs: &[(u32, &[u8])]) -> Vec<u8> {
+        let mut bytes = b"II\x2a\x00".to_vec();
+        bytes.extend_from_slice(&ifds[0].0.to_le_bytes());
+        let mut put = |offset: u32, data: &[u8]| {
+            let end = offset as usize + data.len();
+            if bytes.len() < end {
+                bytes.resize(end, 0);
+            }
+            bytes[offset as usize..end].copy_from_slice(data);
+        };
+        for &(offset, entries, next) in ifds {
+            let mut ifd = (entries.len() as u16).to_le_bytes().to_vec();
+            for &(tag, field_type, value) in entries {
+                ifd.extend_from_slice(&tag.to_le_bytes());
+                ifd.extend_from_slice(&field_type.to_le_bytes());
+                ifd.extend_from_slice(&1u32.to_le_bytes());
+                ifd.extend_from_slice(&value.to_le_bytes());
+            }
+            ifd.extend_from_slice(&next.to_le_bytes());
+            put(offset, &ifd);
+        }
+        for &(offset, data) in blobs {
+            put(offset, data);
+        }
+        bytes
+    }
+
+    fn preview(bytes: Vec<u8>) -> Option<(Vec<u8>, Orientation)> {
+        find_preview(TiffReader::new(Cursor::new(bytes)).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn finds_jpeg_in_sub_ifd() {
+        let bytes = tiff(
+            &[
+                (
+                    8,
+                    &[
+                        (TAG_ORIENTATION, TYPE_SHORT, 6),
+                        (TAG_SUB_IFDS, TYPE_LONG, 64),
+                    ],
+                    0,
+                ),
+                (
+                    64,
+                    &[
+                        (TAG_JPEG_OFFSET, TYPE_LONG, 128),
+                        (TAG_JPEG_LENGTH, TYPE_LONG, BASELINE_JPEG.len() as u32),
+                    ],
+                    0,
+                ),
+            ],
+            &[(128, BASELINE_JPEG)],
+        );
+        assert_eq!(
+            preview(bytes),
+            Some((BASELINE_JPEG.to_vec(), Orientation::Rotate90))
+        );
+    }
+
+    #[test]
+    fn skips_lossless_jpeg() {
+        let bytes = tiff(
+            &[
+                (
+                    8,
+                    &[
+                        (TAG_COMPRESSION, TYPE_SHORT, COMPRESSION_JPEG),
+                        (TAG_STRIP_OFFSETS, TYPE_LONG, 128),
+                        (TAG_STRIP_BYTE_COUNTS, TYPE_LONG, LOSSLESS_JPEG.len() as u32),
+                    ],
+                    64,
+                ),
+                (
+                    64,
+                    &[
+                        (TAG_JPEG_OFFSET, TYPE_LONG, 160),
+                        (TAG_JPEG_LENGTH, TYPE_LONG, BASELINE_JPEG.len() as u32),
+                    ],
+                    0,
+                ),
+            ],
+            &[(128, LOSSLESS_JPEG), (160, BASELINE_JPEG)],
+        );
+        assert_eq!(
+            preview(bytes),
+            Some((BASELINE_JPEG.to_vec(), Orientation::NoTransforms))
+        );
+    }
+
+    #[test]
+    fn stops_at_cycles_and_bad_offsets() {
+        let bytes = tiff(
+            &[
+                (8, &[(TAG_SUB_IFDS, TYPE_LONG, 8)], 64),
+                (
+                    64,
+                    &[
+                        (TAG_JPEG_OFFSET, TYPE_LONG, 4096),
+                        (TAG_JPEG_LENGTH, TYPE_LONG, 16),
+                    ],
+                    8,
+                ),
+            ],
+            &[],
+        );
+        assert_eq!(preview(bytes), None);
+
+        let bytes = tiff(&[(8, &[(TAG_SUB_IFDS, TYPE_LONG, 4096)], 8192)], &[]);
+        assert_eq!(preview(bytes), None);
+    }
+
+    #[test]
+    fn rejects_other_files() {
+        assert!(TiffReader::new(Cursor::new(BASELINE_JPEG.to_vec())).is_err());
+        assert!(TiffReader::new(Cursor::new(vec![])).is_err());
+    }
+}
@@ -0,0 +1,124 @@
+use std::{
+    fs::DirEntry,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        OnceLock,
+    },
+    time::{Duration, Instant},
+};
+
+use rayon::{prelude::*, ThreadPool, ThreadPoolBuilder};
+use regex::{Regex, RegexBuilder};
+
+use crate::{error::AppError, handle::REGEX_SIZE_LIMIT};
+
+// Keeps a search from a broad pattern or a huge docroot from running unbounded.
+const MAX_ENTRIES: usize = 100_000;
+const TIME_LIMIT: Duration = Duration::from_secs(10);
+
+// Searches walk on threads of their own, since a slow client holds up the walk once its
+// results fill up the channel, and listings should not wait for that.
+const THREADS: usize = 4;
+
+static POOL: OnceLock<Option<ThreadPool>> = OnceLock::new();
+
+pub(crate) struct Summary {
+    pub visited: usize,
+    pub matched: usize,
+    pub exhausted: bool,
+}
+
+// Patterns that are not valid regular expressions are searched for as plain substrings.
+pub(crate) fn build_pattern(pattern: &str) -> Result<Regex, AppError> {
+    let build = |pattern: &str| {
+        RegexBuilder::new(pattern)
+            .case_insensitive(true)
+            .size_limit(REGEX_SIZE_LIMIT)
+            .build()
+    };
+    build(pattern)
+        .or_else(|_| build(&regex::escape(pattern)))
+        .map_err(|e| AppError::BadRequest(e.into()))
+}
+
+// Walks one directory level at a time, and hands over the matches of each directory
+// as soon as it is read. The walk stops early when `on_match` returns false.
+pub(crate) fn search(
+    uri: &str,
+    pattern: &Regex,
+    filter_op: impl Fn(&DirEntry) -> bool + Sync + Send,
+    on_match: impl Fn(&str, Vec<DirEntry>) -> bool + Sync + Send,
+) -> Summary {
+    let deadline = Instant::now() + TIME_LIMIT;
+    let visited = AtomicUsize::new(0);
+    let matched = AtomicUsize::new(0);
+    let exhausted = AtomicBool::new(false);
+    let cancelled = AtomicBool::new(false);
+
+    let visit = |uri: &String| -> Vec<String> {
+        if exhausted.load(Ordering::Relaxed) || cancelled.load(Ordering::Relaxed) {
+            return vec![];
+        }
+        let Ok(entries) = std::fs::read_dir(format!(".{}", uri)) else {
+            return vec![];
+        };
+        let entries = entries
+            .flatten()
+            .filter(|e| filter_op(e))
+            .collect::<Vec<_>>();
+        if visited.fetch_add(entries.len(), Ordering::Relaxed) + entries.len() > MAX_ENTRIES
+            || Instant::now() > deadline
+        {
+            exhausted.store(true, Ordering::Relaxed);
+        }
+
+        // Symbolic links are not followed, so that a link cannot lead the walk into a loop.
+        let (dirs, files): (Vec<_>, Vec<_>) = entries
+            .into_iter()
+            .partition(|e| e.file_type().is_ok_and(|t| t.is_dir()));
+        let matches = files
+            .into_iter()
+            .filter(|e| pattern.is_match(&e.file_name().to_string_lossy()))
+            .collect::<Vec<_>>();
+        if !matches.is_empty() {
+            matched.fetch_add(matches.len(), Ordering::Relaxed);
+            if !on_match(uri, matches) {
+                cancelled.store(true, Ordering::Relaxed);
+            }
+        }
+
+        let parent = uri.trim_end_matches('/');
+        dirs.iter()
+            .map(|e| format!("{}/{}/", parent, e.file_name().to_string_lossy()))
+            .collect()
+    };
+
+    let walk = || {
+        let mut targets = vec![uri.to_owned()];
+        while !targets.is_empty() {
+            targets = targets.par_iter().flat_map_iter(visit).collect();
+        }
+    };
+    match pool() {
+        Some(pool) => pool.install(walk),
+        None => walk(),
+    }
+
+    Summary {
+        visited: visited.into_inner(),
+        matched: matched.into_inner(),
+        exhausted: exhausted.into_inner(),
+    }
+}
+
+fn pool() -> Option<&'static ThreadPool> {
+    POOL.get_or_init(|| {
+        ThreadPoolBuilder::new()
+            .num_threads(THREADS)
+            .thread_name(|i| format!("search-{}", i))
+            .build()
+            .map_err(|e| tracing::warn!("Failed to start the search threads: {:?}", e))
+            .ok()
+    })
+    .as_ref()
+}
@@ -0,0 +1,54 @@
+use std::{
+    collections::HashMap,
+    fs,
+    io::Read,
+    path::{Path, PathBuf},
+    sync::{OnceLock, RwLock},
+    time::SystemTime,
+};
+
+const SNIFF_LENGTH: u64 = 8192;
+
+// A sniffed type replaces the one from the extension, so it must never be a type that
+// browsers render as a document, such as HTML or XML.
+const MEDIA_TYPE_PREFIXES: &[&str] = &["image/", "video/", "audio/", "application/pdf"];
+
+type SniffCache = RwLock<HashMap<PathBuf, (SystemTime, Option<&'static str>)>>;
+
+static CACHE: OnceLock<SniffCache> = OnceLock::new();
+
+pub(crate) fn sniff(path: &Path) -> Option<&'static str> {
+    let metadata = fs::metadata(path).ok().filter(|m| m.is_file())?;
+    let modified = metadata.modified().ok()?;
+
+    let cache = CACHE.get_or_init(Default::default);
+    if let Some((cached_at, mime)) = cache.read().unwrap().get(path) {
+        if *cached_at == modified {
+            return *mime;
+        }
+    }
+
+    let mime = read_head(path)
+        .and_then(|head| infer::get(&head))
+        .map(|t| t.mime_type())
+        .filter(|m| is_media_type(m));
+    tracing::trace!("sniffed {} as {:?}", path.display(), mime);
+
+    cache
+        .write()
+        .unwrap()
+        .insert(path.to_owned(), (modified, mime));
+    mime
+}
+
+fn read_head(path: &Path) -> Option<Vec<u8>> {
+    let mut head = vec![];
+    fs::File::open(path)
+        .and_then(|f| f.take(SNIFF_LENGTH).read_to_end(&mut head))
+        .ok()?;
+    Some(head)
+}
+
+fn is_media_type(mime: &str) -> bool {
+    MEDIA_TYPE_PREFIXES.iter().any(|p| mime.starts_with(p)) && !mime.contains("xml")
+}
@@ -0,0 +1,255 @@
+use std::{collections::HashMap, path::Path};
+
+use anyhow::Result;
+
+use crate::model::File;
+
+const VTT_HEADER: &str = "WEBVTT\n\n";
+
+#[derive(Clone)]
+pub(crate) struct Subtitle {
+    file: File,
+    language: Option<String>,
+}
+
+impl Subtitle {
+    pub fn file(&self) -> &File {
+        &self.file
+    }
+
+    pub fn language(&self) -> Option<&str> {
+        self.language.as_deref()
+    }
+
+    pub fn is_vtt(&self) -> bool {
+        self.file.name().to_ascii_lowercase().ends_with(".vtt")
+    }
+}
+
+// Moves `movie.srt` and `movie.<language>.srt` into every `movie.<ext>` video next to them.
+pub(crate) fn attach_sidecars(files: Vec<File>) -> Vec<File> {
+    let (mut subtitles, mut files): (Vec<_>, Vec<_>) =
+        files.into_iter().partition(|f| f.is_subtitle());
+    subtitles.sort_unstable_by(|a, b| a.name().cmp(b.name()));
+    let mut videos: HashMap<String, Vec<usize>> = HashMap::new();
+    for (i, f) in files.iter().enumerate().filter(|(_, f)| f.is_video()) {
+        videos.entry(stem(f.name()).to_owned()).or_default().push(i);
+    }
+
+    let mut orphans = vec![];
+    for file in subtitles {
+        let name = stem(file.name());
+        let matched = match videos.get(name) {
+            Some(indices) => Some((indices, None)),
+            None => name.rsplit_once('.').and_then(|(video, language)| {
+                videos
+                    .get(video)
+                    .map(|indices| (indices, Some(language.to_owned())))
+            }),
+        };
+        match matched {
+            Some((indices, language)) => {
+                for &i in indices {
+                    files[i].push_subtitle(Subtitle {
+                        file: file.clone(),
+                        language: language.clone(),
+                    });
+                }
+            }
+            None => orphans.push(file),
+        }
+    }
+
+    files.append(&mut orphans);
+    files
+}
+
+pub(crate) fn to_vtt(path: &Path) -> Result<String> {
+    let bytes = std::fs::read(path)?;
+    let text = String::from_utf8_lossy(&bytes);
+    let text = text.trim_start_matches('\u{feff}');
+
+    let extension = path
+        .extension()
+        .map(|e| e.to_string_lossy().to_ascii_lowercase());
+    Ok(match extension.as_deref() {
+        Some("ass" | "ssa") => ass_to_vtt(text),
+        Some("vtt") => text.to_owned(),
+        _ => srt_to_vtt(text),
+    })
+}
+
+fn stem(name: &str) -> &str {
+    name.rsplit_once('.').map_or(name, |(stem, _)| stem)
+}
+
+// SRT differs from WebVTT mainly in the header and the decimal separator of timestamps.
+fn srt_to_vtt(text: &str) -> String {
+    let mut vtt = VTT_HEADER.to_owned();
+    for line in text.lines() {
+        if line.contains("-->") {
+            vtt.push_str(&line.replace(',', "."));
+        } else {
+            vtt.push_str(line);
+        }
+        vtt.push('\n');
+    }
+    vtt
+}
+
+fn ass_to_vtt(text: &str) -> String {
+    let mut vtt = VTT_HEADER.to_owned();
+    let mut in_events = false;
+    let mut format = vec![];
+
+    for line in text.lines().map(str::trim) {
+        if line.starts_with('[') {
+            in_events = line.eq_ignore_ascii_case("[events]");
+            continue;
+        }
+        if !in_events {
+            continue;
+        }
+        if let Some(fields) = line.strip_prefix("Format:") {
+            format = fields
+                .split(',')
+                .map(|f| f.trim().to_ascii_lowercase())
+                .collect();
+            continue;
+        }
+        let Some(dialogue) = line.strip_prefix("Dialogue:") else {
+            continue;
+        };
+
+        // The text is the last field and may contain commas itself.
+        let values = dialogue.splitn(format.len(), ',').collect::<Vec<_>>();
+        let field = |name: &str| {
+            format
+                .iter()
+                .position(|f| f == name)
+                .and_then(|i| values.get(i))
+                .map(|v| v.trim())
+        };
+        if let (Some(start), Some(end), Some(text)) = (
+            field("start").and_then(ass_time),
+            field("end").and_then(ass_time),
+            field("text"),
+        ) {
+            vtt.push_str(&format!("{} --> {}\n{}\n\n", start, end, ass_text(text)));
+        }
+    }
+    vtt
+}
+
+// ASS uses `H:MM:SS.cc` with centiseconds.
+fn ass_time(time: &str) -> Option<String> {
+    let (hours, rest) = time.split_once(':')?;
+    let (minutes, rest) = rest.split_once(':')?;
+    let (seconds, centiseconds) = rest.split_once('.')?;
+    Some(format!(
+        "{:02}:{:02}:{:02}.{:03}",
+        hours.parse::<u32>().ok()?,
+        minutes.parse::<u32>().ok()?,
+        seconds.parse::<u32>().ok()?,
+        centiseconds.parse::<u32>().ok()? * 10
+    ))
+}
+
+// Drops override blocks such as `{\i1}` and escapes what WebVTT would read as markup.
+fn ass_text(text: &str) -> String {
+    let mut plain = String::new();
+    let mut in_override = false;
+    for c in text.chars() {
+        match c {
+            '{' => in_override = true,
+            '}' if in_override => in_override = false,
+            _ if in_override => {}
+            '&' => plain.push_str("&amp;"),
+            '<' => plain.push_str("&lt;"),
+            c => plain.push(c),
+        }
+    }
+
+    plain
+        .replace("\\N", "\n")
+        .replace("\\n", "\n")
+        .replace("\\h", " ")
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn attaches_sidecars_to_every_video_with_the_stem() {
+        let files = [
+            "movie.mp4",
+            "movie.webm",
+            "movie.srt",
+            "movie.en.vtt",
+            "other.srt",
+        ]
+        .iter()
+        .map(|name| File::new(Path::new(&format!("./{}", name)), None).unwrap())
+        .collect();
+        let files = attach_sidecars(files);
+
+        let names = files.iter().map(|f| f.name()).collect::<Vec<_>>();
+        assert_eq!(names, ["movie.mp4", "movie.webm", "other.srt"]);
+        for video in &files[..2] {
+            let subtitles = video
+                .subtitles()
+                .iter()
+                .map(|s| (s.file().name(), s.language()))
+                .collect::<Vec<_>>();
+            assert_eq!(
+                subtitles,
+                [("movie.en.vtt", Some("en")), ("movie.srt", None)]
+            );
+        }
+    }
+
+    #[test]
+    fn converts_srt() {
+        let srt = "1\r\n00:00:01,500 --> 00:00:02,000\r\nHello, world\r\n\r\n2\r\n00:01:00,000 --> 00:01:01,250\r\n<i>Bye</i>\r\n";
+        assert_eq!(
+            srt_to_vtt(srt),
+            "WEBVTT\n\n1\n00:00:01.500 --> 00:00:02.000\nHello, world\n\n2\n00:01:00.000 --> 00:01:01.250\n<i>Bye</i>\n"
+        );
+    }
+
+    #[test]
+    fn converts_ass_dialogues() {
+        let ass = "[Script Info]\n\
+                   Title: Dialogue: 0,0:00:00.00,0:00:09.00,Default,,0,0,0,,Not an event\n\
+                   \n\
+                   [Events]\n\
+                   Format: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text\n\
+                   Comment: 0,0:00:00.00,0:00:01.00,Default,,0,0,0,,Hidden\n\
+                   Dialogue: 0,0:00:01.50,0:00:03.00,Default,,0,0,0,,{\\i1}Hello{\\i0}, world\\NBye\n\
+                   Dialogue: 0,1:02:03.04,1:02:05.00,Default,,0,0,0,,a < b & c\\hd\n";
+        assert_eq!(
+            ass_to_vtt(ass),
+            "WEBVTT\n\n\
+             00:00:01.500 --> 00:00:03.000\nHello, world\nBye\n\n\
+             01:02:03.040 --> 01:02:05.000\na &lt; b &amp; c d\n\n"
+        );
+    }
+
+    #[test]
+    fn follows_the_ass_format_line() {
+        let ass = "[Events]\n\
+                   Format: Start, End, Text\n\
+                   Dialogue: 0:00:01.00,0:00:02.00,Text, with commas\n\
+                   Dialogue: broken,0:00:02.00,Skipped\n";
+        assert_eq!(
+            ass_to_vtt(ass),
+            "WEBVTT\n\n00:00:01.000 --> 00:00:02.000\nText, with commas\n\n"
+        );
+    }
+}
@@ -0,0 +1,365 @@
+use std::{
+    path::Path,
+    sync::{Arc, OnceLock},
+};
+
+use anyhow::{anyhow, Result};
+use image::{DynamicImage, RgbaImage};
+use quick_xml::{
+    events::{BytesStart, Event},
+    Reader, Writer,
+};
+use resvg::{
+    tiny_skia::{Pixmap, Transform},
+    usvg::{fontdb::Database, ImageHrefResolver, Options, Tree},
+};
+
+// Applied to every SVG response, so that opening one directly cannot run scripts in this origin.
+pub(crate) const CONTENT_SECURITY_POLICY: &str =
+    "sandbox; default-src 'none'; style-src 'unsafe-inline'; img-src data:";
+
+const SVG_EXTENSIONS: &[&str] = &["svg"];
+
+const DROPPED_ELEMENTS: &[&str] = &[
+    "script",
+    "foreignobject",
+    "iframe",
+    "embed",
+    "object",
+    "handler",
+    "listener",
+];
+
+const ANIMATION_ELEMENTS: &[&str] = &["animate", "animatemotion", "animatetransform", "set"];
+
+// The longest side of rasterized images, which matches the largest thumbnail size.
+const RASTER_SIZE: f32 = 1024.0;
+
+static FONTS: OnceLock<Arc<Database>> = OnceLock::new();
+
+pub(crate) fn is_svg(path: &Path) -> bool {
+    path.extension()
+        .map(|e| e.to_string_lossy().to_ascii_lowercase())
+        .is_some_and(|e| SVG_EXTENSIONS.contains(&e.as_str()))
+}
+
+// Strips scripts, event handlers and references to anything outside the document.
+pub(crate) fn sanitize(path: &Path) -> Result<(Vec<u8>, &'static str)> {
+    Ok((sanitize_bytes(&std::fs::read(path)?)?, "svg"))
+}
+
+fn sanitize_bytes(source: &[u8]) -> Result<Vec<u8>> {
+    let mut reader = Reader::from_reader(source);
+    let mut writer = Writer::new(vec![]);
+    let mut skip_depth = 0;
+    // Everything up to the end of a `<style>` element is CSS, whatever elements it contains.
+    let mut style_depth = 0_usize;
+
+    loop {
+        let event = reader.read_event()?;
+        if skip_depth > 0 {
+            match event {
+                Event::Start(_) => skip_depth += 1,
+                Event::End(_) => skip_depth -= 1,
+                Event::Eof => break,
+                _ => {}
+            }
+            continue;
+        }
+
+        match event {
+            Event::Start(e) if is_dropped(&e) => skip_depth = 1,
+            Event::Empty(e) if is_dropped(&e) => {}
+            Event::Start(e) => {
+                if style_depth > 0 || local_name(&e) == "style" {
+                    style_depth += 1;
+                }
+                writer.write_event(Event::Start(sanitize_attributes(&e)?))?;
+            }
+            Event::Empty(e) => writer.write_event(Event::Empty(sanitize_attributes(&e)?))?,
+            Event::End(e) => {
+                style_depth = style_depth.saturating_sub(1);
+                writer.write_event(Event::End(e))?;
+            }
+            Event::Text(e) if style_depth > 0 && !is_safe_css(&e.unescape()?) => {}
+            Event::CData(e) if style_depth > 0 && !is_safe_css(&String::from_utf8_lossy(&e)) => {}
+            // DTDs can declare entities that expand to markup, and processing
+            // instructions can pull in external stylesheets.
+            Event::DocType(_) | Event::PI(_) => {}
+            Event::Eof => break,
+            event => writer.write_event(event)?,
+        }
+    }
+    Ok(writer.into_inner())
+}
+
+pub(crate) fn rasterize(path: &Path) -> Result<DynamicImage> {
+    let options = Options {
+        fontdb: FONTS
+            .get_or_init(|| {
+                let mut fonts = Database::new();
+                fonts.load_system_fonts();
+                Arc::new(fonts)
+            })
+            .clone(),
+        image_href_resolver: ImageHrefResolver {
+            resolve_string: Box::new(|_, _| None),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let tree = Tree::from_data(&std::fs::read(path)?, &options)?;
+
+    let size = tree.size();
+    let scale = RASTER_SIZE / size.width().max(size.height());
+    let (width, height) = (
+        (size.width() * scale).ceil() as u32,
+        (size.height() * scale).ceil() as u32,
+    );
+    let mut pixmap =
+        Pixmap::new(width, height).ok_or_else(|| anyhow!("Invalid size of {}", path.display()))?;
+    resvg::render(
+        &tree,
+        Transform::from_scale(scale, scale),
+        &mut pixmap.as_mut(),
+    );
+
+    let pixels = pixmap
+        .pixels()
+        .iter()
+        .flat_map(|p| {
+            let c = p.demultiply();
+            [c.red(), c.green(), c.blue(), c.alpha()]
+        })
+        .collect();
+    let image = RgbaImage::from_raw(width, height, pixels)
+        .ok_or_else(|| anyhow!("Failed to rasterize {}", path.display()))?;
+    Ok(DynamicImage::ImageRgba8(image))
+}
+
+fn local_name(element: &BytesStart) -> String {
+    String::from_utf8_lossy(element.local_name().as_ref()).to_ascii_lowercase()
+}
+
+fn is_dropped(element: &BytesStart) -> bool {
+    let name = local_name(element);
+    if DROPPED_ELEMENTS.contains(&name.as_str()) {
+        return true;
+    }
+
+    // Animations can set an event handler or a link target after loading.
+    ANIMATION_ELEMENTS.contains(&name.as_str())
+        && element.attributes().flatten().any(|a| {
+            let target = String::from_utf8_lossy(&a.value).to_ascii_lowercase();
+            a.key.local_name().as_ref() == b"attributeName"
+                && (target.ends_with("href") || target.starts_with("on"))
+        })
+}
+
+fn sanitize_attributes<'a>(element: &'a BytesStart) -> Result<BytesStart<'a>> {
+    let mut sanitized = element.to_owned();
+    sanitized.clear_attributes();
+    for attribute in element.attributes() {
+        let attribute = attribute?;
+        let name =
+            String::from_utf8_lossy(attribute.key.local_name().as_ref()).to_ascii_lowercase();
+        let value = attribute.unescape_value()?;
+        let is_safe = match name.as_str() {
+            n if n.starts_with("on") => false,
+            "href" | "src" => is_local_reference(&value),
+            "style" => is_safe_css(&value),
+            // Presentation attributes such as `fill` and `filter` take `url()` references too.
+            _ => !value.to_ascii_lowercase().contains("javascript:") && is_safe_css(&value),
+        };
+        if is_safe {
+            sanitized.push_attribute(attribute);
+        }
+    }
+    Ok(sanitized)
+}
+
+fn is_local_reference(value: &str) -> bool {
+    let value = value.trim().to_ascii_lowercase();
+    // SVG documents in data URLs could carry scripts of their own.
+    value.starts_with('#')
+        || (value.starts_with("data:image/") && !value.starts_with("data:image/svg"))
+}
+
+fn is_safe_css(css: &str) -> bool {
+    let css = css.to_ascii_lowercase();
+    // Escapes such as `\75 rl(` would hide the keywords below.
+    !css.contains('\\')
+        && !css.contains("@import")
+        && !css.contains("expression(")
+        && css.match_indices("url(").all(|(i, _)| {
+            css[i + 4..]
+                .trim_start_matches(|c: char| c.is_whitespace() || c == '"' || c == '\'')
+                .starts_with('#')
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sanitized(svg: &str) -> String {
+        String::from_utf8(sanitize_bytes(svg.as_bytes()).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn drops_scripts() {
+        assert_eq!(
+            sanitized(r#"<svg><script>alert(1)</script><SCRIPT/><circle r="1"/></svg>"#),
+            r#"<svg><circle r="1"/></svg>"#
+        );
+        assert_eq!(
+            sanitized(
+                r#"<svg xmlns:h="http://www.w3.org/1999/xhtml"><h:script>alert(1)</h:script></svg>"#
+            ),
+            r#"<svg xmlns:h="http://www.w3.org/1999/xhtml"></svg>"#
+        );
+    }
+
+    #[test]
+    fn drops_event_handlers() {
+        assert_eq!(
+            sanitized(r#"<svg onload="alert(1)"><rect onClick="alert(1)" width="1"/></svg>"#),
+            r#"<svg><rect width="1"/></svg>"#
+        );
+    }
+
+    #[test]
+    fn drops_javascript_urls() {
+        assert_eq!(
+            sanitized(r#"<svg><a href="javascript:alert(1)"><text>x</text></a></svg>"#),
+            r#"<svg><a><text>x</text></a></svg>"#
+        );
+        assert_eq!(
+            sanitized(r#"<svg><a xlink:href=" JavaScript&#58;alert(1)"/></svg>"#),
+            r#"<svg><a/></svg>"#
+        );
+        assert_eq!(
+            sanitized(r##"<svg><a href="#top" target="javascript:alert(1)"/></svg>"##),
+            r##"<svg><a href="#top"/></svg>"##
+        );
+    }
+
+    #[test]
+    fn keeps_only_data_urls_of_raster_images() {
+        assert_eq!(
+            sanitized(
+                r#"<svg><image href="data:text/html,&lt;script&gt;alert(1)&lt;/script&gt;"/></svg>"#
+            ),
+            r#"<svg><image/></svg>"#
+        );
+        assert_eq!(
+            sanitized(r#"<svg><image href="data:image/svg+xml;base64,PHN2Zy8+"/></svg>"#),
+            r#"<svg><image/></svg>"#
+        );
+        assert_eq!(
+            sanitized(r#"<svg><image href="https://example.com/a.png"/></svg>"#),
+            r#"<svg><image/></svg>"#
+        );
+        assert_eq!(
+            sanitized(r#"<svg><image href="data:image/png;base64,AAAA"/></svg>"#),
+            r#"<svg><image href="data:image/png;base64,AAAA"/></svg>"#
+        );
+    }
+
+    #[test]
+    fn drops_animations_of_links_and_handlers() {
+        assert_eq!(
+            sanitized(r#"<svg><a><set attributeName="href" to="javascript:alert(1)"/></a></svg>"#),
+            r#"<svg><a></a></svg>"#
+        );
+        assert_eq!(
+            sanitized(
+                r#"<svg><animate attributeName="xlink:href" values="x"><x/></animate></svg>"#
+            ),
+            r#"<svg></svg>"#
+        );
+        assert_eq!(
+            sanitized(r#"<svg><set attributeName="onclick" to="alert(1)"/></svg>"#),
+            r#"<svg></svg>"#
+        );
+        assert_eq!(
+            sanitized(r#"<svg><set attributeName="fill" to="red"/></svg>"#),
+            r#"<svg><set attributeName="fill" to="red"/></svg>"#
+        );
+    }
+
+    #[test]
+    fn drops_foreign_objects() {
+        assert_eq!(
+            sanitized(
+                r#"<svg><foreignObject><div><iframe src="https://example.com"/></div></foreignObject><g/></svg>"#
+            ),
+            r#"<svg><g/></svg>"#
+        );
+    }
+
+    #[test]
+    fn drops_external_css() {
+        assert_eq!(
+            sanitized("<svg><style>@import url(https://example.com/a.css);</style></svg>"),
+            "<svg><style></style></svg>"
+        );
+        assert_eq!(
+            sanitized(
+                "<svg><style><![CDATA[rect { fill: url('https://example.com/a') }]]></style></svg>"
+            ),
+            "<svg><style></style></svg>"
+        );
+        assert_eq!(
+            sanitized(
+                r#"<svg><rect style="fill: url( &quot;https://example.com/a&quot; )"/></svg>"#
+            ),
+            "<svg><rect/></svg>"
+        );
+        assert_eq!(
+            sanitized(r#"<svg><rect style="fill: \75 rl(https://example.com/a)"/></svg>"#),
+            "<svg><rect/></svg>"
+        );
+        assert_eq!(
+            sanitized(r##"<svg><rect style="fill: url(#gradient)"/></svg>"##),
+            r##"<svg><rect style="fill: url(#gradient)"/></svg>"##
+        );
+        assert_eq!(
+            sanitized("<svg><style>rect { fill: red }</style></svg>"),
+            "<svg><style>rect { fill: red }</style></svg>"
+        );
+        assert_eq!(
+            sanitized("<svg><style><x></x>@import url(https://example.com/a.css);</style></svg>"),
+            "<svg><style><x></x></style></svg>"
+        );
+        assert_eq!(
+            sanitized("<svg><style><x/></style><text>@import</text></svg>"),
+            "<svg><style><x/></style><text>@import</text></svg>"
+        );
+    }
+
+    #[test]
+    fn drops_external_references_in_presentation_attributes() {
+        assert_eq!(
+            sanitized(
+                r#"<svg><rect fill="url(https://example.com/x.svg#g)" filter="url(http://example.com/f.svg#f)"/></svg>"#
+            ),
+            "<svg><rect/></svg>"
+        );
+        assert_eq!(
+            sanitized(r##"<svg><rect fill="url(#g)" stroke="red"/></svg>"##),
+            r##"<svg><rect fill="url(#g)" stroke="red"/></svg>"##
+        );
+    }
+
+    #[test]
+    fn drops_doctypes_and_processing_instructions() {
+        let svg = sanitized(concat!(
+            r#"<?xml version="1.0"?>"#,
+            r#"<?xml-stylesheet href="https://example.com/a.css"?>"#,
+            r#"<!DOCTYPE svg [<!ENTITY x "<script>alert(1)</script>">]>"#,
+            r#"<svg><text>&x;</text></svg>"#,
+        ));
+        assert_eq!(svg, r#"<?xml version="1.0"?><svg><text>&x;</text></svg>"#);
+    }
+}
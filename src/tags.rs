@@ -0,0 +1,123 @@
+use std::{path::Path, time::Duration};
+
+use anyhow::{anyhow, Result};
+use lofty::{
+    file::TaggedFile,
+    picture::{MimeType, PictureType},
+    prelude::*,
+    probe::Probe,
+};
+
+use crate::thumbnail;
+
+#[derive(Clone, Default)]
+pub(crate) struct MediaTags {
+    title: Option<String>,
+    artist: Option<String>,
+    album: Option<String>,
+    duration: Option<Duration>,
+    has_cover: bool,
+}
+
+impl MediaTags {
+    pub fn read(path: &Path) -> Option<Self> {
+        match Self::read_from_file(path) {
+            Ok(tags) => Some(tags),
+            Err(e) => {
+                tracing::trace!("Failed to read tags of {}: {}", path.display(), e);
+                None
+            }
+        }
+    }
+
+    fn read_from_file(path: &Path) -> Result<Self> {
+        let tagged_file = read_tagged_file(path)?;
+        let tag = tagged_file
+            .primary_tag()
+            .or_else(|| tagged_file.first_tag());
+        let duration = tagged_file.properties().duration();
+
+        Ok(Self {
+            title: tag.and_then(|t| t.title()).map(|t| t.into_owned()),
+            artist: tag.and_then(|t| t.artist()).map(|t| t.into_owned()),
+            album: tag.and_then(|t| t.album()).map(|t| t.into_owned()),
+            duration: (!duration.is_zero()).then_some(duration),
+            has_cover: tagged_file.tags().iter().any(|t| !t.pictures().is_empty()),
+        })
+    }
+
+    pub fn title(&self) -> Option<&str> {
+        self.title.as_deref()
+    }
+
+    pub fn artist(&self) -> Option<&str> {
+        self.artist.as_deref()
+    }
+
+    pub fn album(&self) -> Option<&str> {
+        self.album.as_deref()
+    }
+
+    pub fn has_cover(&self) -> bool {
+        self.has_cover
+    }
+
+    pub fn duration_text(&self) -> Option<String> {
+        let seconds = self.duration?.as_secs();
+        let (hours, minutes, seconds) = (seconds / 3600, seconds / 60 % 60, seconds % 60);
+        if hours > 0 {
+            Some(format!("{}:{:02}:{:02}", hours, minutes, seconds))
+        } else {
+            Some(format!("{}:{:02}", minutes, seconds))
+        }
+    }
+
+    pub fn summary(&self) -> Vec<String> {
+        [
+            self.title.clone(),
+            self.artist.clone(),
+            self.album.clone(),
+            self.duration_text(),
+        ]
+        .into_iter()
+        .flatten()
+        .collect()
+    }
+}
+
+pub(crate) fn extract_cover(path: &Path, size: Option<u32>) -> Result<(Vec<u8>, &'static str)> {
+    let tagged_file = read_tagged_file(path)?;
+    let pictures = tagged_file
+        .tags()
+        .iter()
+        .flat_map(|t| t.pictures())
+        .collect::<Vec<_>>();
+    let picture = pictures
+        .iter()
+        .find(|p| p.pic_type() == PictureType::CoverFront)
+        .or_else(|| pictures.first())
+        .ok_or_else(|| anyhow!("No cover art in {}", path.display()))?;
+
+    let extension = match picture.mime_type() {
+        Some(MimeType::Png) => "png",
+        Some(MimeType::Jpeg) => "jpg",
+        Some(MimeType::Tiff) => "tiff",
+        Some(MimeType::Bmp) => "bmp",
+        Some(MimeType::Gif) => "gif",
+        _ => image::guess_format(picture.data())?
+            .extensions_str()
+            .first()
+            .copied()
+            .ok_or_else(|| anyhow!("Unknown cover art format in {}", path.display()))?,
+    };
+    match size {
+        Some(size) => {
+            thumbnail::generate_from_image(image::load_from_memory(picture.data())?, size)
+        }
+        None => Ok((picture.data().to_vec(), extension)),
+    }
+}
+
+fn read_tagged_file(path: &Path) -> Result<TaggedFile> {
+    Ok(Probe::open(path)?.guess_file_type()?.read()?)
+}
@@ -1,10 +1,14 @@
 use anyhow::anyhow;
 use askama::Template;
 use axum::response::{Html, IntoResponse, Response};
+use percent_encoding::{percent_decode_str, utf8_percent_encode};
 
 use crate::{
+    convert::DEFAULT_CONVERSION_FORMAT,
     error::AppError,
-    model::{File, FilesContainer},
+    model::{File, FilesContainer, FRAGMENT},
+    subtitle::Subtitle,
+    thumbnail::DEFAULT_THUMBNAIL_SIZE,
 };
 
 pub(crate) const WATERMARK: &str = "<!-- Generated by Rattice -->";
@@ -16,17 +20,35 @@ pub(crate) struct RatticeTemplate<'a> {
     query: &'a str,
     containers: Vec<FilesContainer>,
     lazy: bool,
+    animate: bool,
+    thumbnail: bool,
+    rasterize_svg: bool,
     title_prefix: &'a str,
     generate_static: bool,
     add_watermark: bool,
+    part: PagePart,
+    search: Option<&'a str>,
+    notice: Option<String>,
+}
+
+// Lets a page be streamed as a header, any number of containers and a footer.
+pub(crate) enum PagePart {
+    Whole,
+    Header,
+    Containers,
+    Footer,
 }
 
 impl<'a> RatticeTemplate<'a> {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         uri: &'a str,
         query: &'a str,
         containers: Vec<FilesContainer>,
         lazy: bool,
+        animate: bool,
+        thumbnail: bool,
+        rasterize_svg: bool,
         title_prefix: &'a str,
         generate_static: bool,
         add_watermark: bool,
@@ -36,9 +58,398 @@ impl<'a> RatticeTemplate<'a> {
             query,
             containers,
             lazy,
+            animate,
+            thumbnail,
+            rasterize_svg,
             title_prefix,
             generate_static,
             add_watermark,
+            part: PagePart::Whole,
+            search: None,
+            notice: None,
+        }
+    }
+
+    pub fn with_part(mut self, part: PagePart) -> Self {
+        self.part = part;
+        self
+    }
+
+    pub fn with_search(mut self, search: &'a str) -> Self {
+        self.search = Some(search);
+        self
+    }
+
+    pub fn with_notice(mut self, notice: String) -> Self {
+        self.notice = Some(notice);
+        self
+    }
+
+    fn renders_header(&self) -> bool {
+        matches!(self.part, PagePart::Whole | PagePart::Header)
+    }
+
+    fn renders_containers(&self) -> bool {
+        matches!(self.part, PagePart::Whole | PagePart::Containers)
+    }
+
+    fn renders_footer(&self) -> bool {
+        matches!(self.part, PagePart::Whole | PagePart::Footer)
+    }
+
+    fn search_value(&self) -> &str {
+        self.search.unwrap_or_default()
+    }
+
+    // The search form passes on the parameters of the listing, such as the filters and the order.
+    fn search_form_params(&self) -> Vec<(String, String)> {
+        let decode = |s: &str| {
+            percent_decode_str(&s.replace('+', " "))
+                .decode_utf8_lossy()
+                .into_owned()
+        };
+        self.query_params()
+            .into_iter()
+            .filter(|p| !p.starts_with("search="))
+            .map(|p| {
+                let (name, value) = p.split_once('=').unwrap_or((p, ""));
+                (decode(name), decode(value))
+            })
+            .collect()
+    }
+
+    fn container_link(&self, container: &FilesContainer) -> String {
+        format!(
+            "{}{}",
+            utf8_percent_encode(container.uri(), FRAGMENT),
+            self.query
+        )
+    }
+
+    fn image_link(&self, file: &File, link_path: &str) -> String {
+        if self.generate_static {
+            link_path.to_owned()
+        } else {
+            display_link(file, link_path)
+        }
+    }
+
+    // Tiles open the viewer, which keeps the sort order and the filters of the listing.
+    fn view_link(&self, file: &File, link_path: &str) -> String {
+        if self.generate_static {
+            return self.image_link(file, link_path);
+        }
+        let mut params = vec!["view"];
+        params.extend(
+            self.query_params()
+                .into_iter()
+                .filter(|p| !p.starts_with("search=")),
+        );
+        format!("{}?{}", link_path, params.join("&"))
+    }
+
+    fn document_link(&self, file: &File, link_path: &str) -> String {
+        if !self.generate_static && file.is_markdown() {
+            format!("{}?render", link_path)
+        } else {
+            link_path.to_owned()
+        }
+    }
+
+    fn cover_src(&self, file: &File, link_path: &str) -> Option<String> {
+        if self.generate_static || !file.has_cover() {
+            None
+        } else if self.thumbnail {
+            Some(format!("{}?cover={}", link_path, DEFAULT_THUMBNAIL_SIZE))
+        } else {
+            Some(format!("{}?cover", link_path))
+        }
+    }
+
+    // Directories without any image drop the broken image in the `onerror` handler.
+    fn dir_cover_src(&self, file: &File) -> Option<String> {
+        if self.generate_static || file.name() == ".." {
+            return None;
+        }
+        let mut params = self
+            .query
+            .trim_start_matches('?')
+            .split('&')
+            .filter(|p| {
+                ["order=", "reverse=", "seed="]
+                    .iter()
+                    .any(|k| p.starts_with(k))
+            })
+            .map(|p| p.to_owned())
+            .collect::<Vec<_>>();
+        params.push(if self.thumbnail {
+            format!("cover={}", DEFAULT_THUMBNAIL_SIZE)
+        } else {
+            "cover".to_owned()
+        });
+        Some(format!("/{}?{}", file.path(), params.join("&")))
+    }
+
+    // Links keep the other query parameters, so that sorting and filtering carry over.
+    fn page_links(&self, container: &FilesContainer) -> Vec<PageLink> {
+        let href = |page: usize| Some(self.page_link(container, page));
+
+        let page = container.page();
+        let mut links = vec![];
+        if page > 1 {
+            links.push(PageLink::new("\u{ab} prev", href(page - 1), false));
+        }
+        for number in container.page_numbers() {
+            links.push(match number {
+                Some(n) if n == page => PageLink::new(n.to_string(), None, true),
+                Some(n) => PageLink::new(n.to_string(), href(n), false),
+                None => PageLink::new("\u{2026}", None, false),
+            });
+        }
+        if page < container.page_count() {
+            links.push(PageLink::new("next \u{bb}", href(page + 1), false));
+        }
+        links
+    }
+
+    // Subdirectories are not paged in place, so the links to their pages lead to their own
+    // listings.
+    fn page_link(&self, container: &FilesContainer, page: usize) -> String {
+        let mut params = self.query_params();
+        let page = format!("page={}", page);
+        params.push(&page);
+        format!(
+            "{}?{}",
+            utf8_percent_encode(container.uri(), FRAGMENT),
+            params.join("&")
+        )
+    }
+
+    // The page number belongs to the current directory, so it is left out of the links.
+    fn breadcrumbs(&self) -> Vec<PageLink> {
+        let segments = self
+            .uri
+            .split('/')
+            .filter(|s| !s.is_empty())
+            .collect::<Vec<_>>();
+        let params = self.query_params();
+        let query = if params.is_empty() {
+            String::new()
+        } else {
+            format!("?{}", params.join("&"))
+        };
+
+        let mut href = "/".to_owned();
+        let mut links = vec![];
+        for i in 0..=segments.len() {
+            let label = match i {
+                0 => "/".to_owned(),
+                i => {
+                    href.push_str(&utf8_percent_encode(segments[i - 1], FRAGMENT).to_string());
+                    href.push('/');
+                    format!("{}/", segments[i - 1])
+                }
+            };
+            let link = if i == segments.len() {
+                None
+            } else if self.generate_static {
+                Some(format!("{}index.html", "../".repeat(segments.len() - i)))
+            } else {
+                Some(format!("{}{}", href, query))
+            };
+            links.push(PageLink::new(label, link, i == segments.len()));
+        }
+        links
+    }
+
+    fn query_params(&self) -> Vec<&str> {
+        self.query
+            .trim_start_matches('?')
+            .split('&')
+            .filter(|p| !p.is_empty() && !p.starts_with("page="))
+            .collect()
+    }
+
+    fn subtitle_src(&self, subtitle: &Subtitle) -> Option<String> {
+        let file = subtitle.file();
+        if self.generate_static {
+            subtitle.is_vtt().then(|| file.to_static_uri(self.uri))
+        } else if subtitle.is_vtt() {
+            Some(format!("/{}", file.path()))
+        } else {
+            Some(format!("/{}?vtt", file.path()))
+        }
+    }
+
+    // Animated images show a still first frame until hovered, unless animation is on.
+    fn image_src(&self, file: &File, link_path: &str) -> String {
+        if file.is_animated() && self.animate {
+            self.image_link(file, link_path)
+        } else if self.thumbnail
+            && (file.is_decodable_image() || file.is_raw() || (self.rasterize_svg && file.is_svg()))
+        {
+            format!("{}?thumbnail={}", link_path, DEFAULT_THUMBNAIL_SIZE)
+        } else if file.is_animated() {
+            format!("{}?format={}", link_path, DEFAULT_CONVERSION_FORMAT.name())
+        } else {
+            self.image_link(file, link_path)
+        }
+    }
+}
+
+// Browsers cannot show RAW and some other formats, so those are served converted.
+pub(crate) fn display_link(file: &File, link_path: &str) -> String {
+    if file.is_raw() {
+        format!("{}?preview", link_path)
+    } else if file.needs_conversion() {
+        format!("{}?format={}", link_path, DEFAULT_CONVERSION_FORMAT.name())
+    } else {
+        link_path.to_owned()
+    }
+}
+
+struct PageLink {
+    label: String,
+    href: Option<String>,
+    current: bool,
+}
+
+impl PageLink {
+    fn new<T>(label: T, href: Option<String>, current: bool) -> Self
+    where
+        T: Into<String>,
+    {
+        Self {
+            label: label.into(),
+            href,
+            current,
+        }
+    }
+}
+
+#[derive(Template)]
+#[template(path = "viewer.html")]
+pub(crate) struct ViewerTemplate<'a> {
+    uri: &'a str,
+    query: &'a str,
+    dir_uri: &'a str,
+    file: File,
+    prev: Option<File>,
+    next: Option<File>,
+    position: usize,
+    count: usize,
+    title_prefix: &'a str,
+}
+
+impl<'a> ViewerTemplate<'a> {
+    // `files` are the viewable files of the listing, and `index` points to the one to show.
+    pub fn new(
+        uri: &'a str,
+        query: &'a str,
+        dir_uri: &'a str,
+        mut files: Vec<File>,
+        index: usize,
+        title_prefix: &'a str,
+    ) -> Self {
+        let count = files.len();
+        let next = files.get(index + 1).cloned();
+        let prev = index.checked_sub(1).and_then(|i| files.get(i)).cloned();
+        Self {
+            uri,
+            query,
+            dir_uri,
+            file: files.swap_remove(index),
+            prev,
+            next,
+            position: index + 1,
+            count,
+            title_prefix,
+        }
+    }
+
+    fn src(&self, file: &File) -> String {
+        display_link(file, &format!("/{}", file.path()))
+    }
+
+    fn view_link(&self, file: &File) -> String {
+        format!("/{}{}", file.path(), self.query)
+    }
+
+    fn back_link(&self) -> String {
+        let params = self
+            .query
+            .trim_start_matches('?')
+            .split('&')
+            .filter(|p| !p.is_empty() && *p != "view" && !p.starts_with("view="))
+            .collect::<Vec<_>>();
+        let path = utf8_percent_encode(self.dir_uri, FRAGMENT);
+        if params.is_empty() {
+            path.to_string()
+        } else {
+            format!("{}?{}", path, params.join("&"))
+        }
+    }
+
+    // Only images are worth fetching ahead, videos are too large.
+    fn preload_srcs(&self) -> Vec<String> {
+        [&self.prev, &self.next]
+            .into_iter()
+            .flatten()
+            .filter(|f| f.is_image() || f.is_raw())
+            .map(|f| self.src(f))
+            .collect()
+    }
+}
+
+#[derive(Template)]
+#[template(path = "slideshow.html")]
+pub(crate) struct SlideshowTemplate<'a> {
+    uri: &'a str,
+    playlist: &'a str,
+    interval: f64,
+    fade: f64,
+    refresh: f64,
+    seed: Option<u64>,
+    title_prefix: &'a str,
+}
+
+impl<'a> SlideshowTemplate<'a> {
+    // Shuffles with `seed` when it is given.
+    pub fn new(
+        uri: &'a str,
+        playlist: &'a str,
+        interval: f64,
+        fade: f64,
+        refresh: f64,
+        seed: Option<u64>,
+        title_prefix: &'a str,
+    ) -> Self {
+        Self {
+            uri,
+            playlist,
+            interval,
+            fade,
+            refresh,
+            seed,
+            title_prefix,
+        }
+    }
+}
+
+#[derive(Template)]
+#[template(path = "markdown.html")]
+pub(crate) struct MarkdownTemplate<'a> {
+    uri: &'a str,
+    content: &'a str,
+    title_prefix: &'a str,
+}
+
+impl<'a> MarkdownTemplate<'a> {
+    pub fn new(uri: &'a str, content: &'a str, title_prefix: &'a str) -> Self {
+        Self {
+            uri,
+            content,
+            title_prefix,
         }
     }
 }
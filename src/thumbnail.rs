@@ -0,0 +1,39 @@
+use std::path::Path;
+
+use anyhow::Result;
+use image::DynamicImage;
+
+use crate::convert::{self, OutputFormat};
+
+pub(crate) const THUMBNAIL_SIZES: &[u32] = &[128, 256, 512, 1024];
+pub(crate) const DEFAULT_THUMBNAIL_SIZE: u32 = 512;
+
+pub(crate) fn bucket(size: u32) -> u32 {
+    THUMBNAIL_SIZES
+        .iter()
+        .copied()
+        .find(|&b| b >= size)
+        .unwrap_or(THUMBNAIL_SIZES[THUMBNAIL_SIZES.len() - 1])
+}
+
+pub(crate) fn generate(path: &Path, size: u32) -> Result<(Vec<u8>, &'static str)> {
+    generate_from_image(convert::decode(path)?, size)
+}
+
+pub(crate) fn generate_from_image(
+    image: DynamicImage,
+    size: u32,
+) -> Result<(Vec<u8>, &'static str)> {
+    let thumbnail = if image.width() > size || image.height() > size {
+        image.thumbnail(size, size)
+    } else {
+        image
+    };
+
+    let format = if thumbnail.color().has_alpha() {
+        OutputFormat::Png
+    } else {
+        OutputFormat::Jpeg
+    };
+    Ok((convert::encode(&thumbnail, format)?, format.extension()))
+}